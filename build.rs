@@ -0,0 +1,32 @@
+//! Captures the git commit and build time served by `GET /version`.
+
+use chrono::SecondsFormat;
+use std::env;
+use std::process::Command;
+
+/// Short hash of `HEAD`, or `GIT_COMMIT` when building outside a checkout (e.g. in CI).
+fn git_commit() -> String {
+    if let Ok(commit) = env::var("GIT_COMMIT") {
+        return commit;
+    }
+    Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|commit| commit.trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+fn main() {
+    println!("cargo:rustc-env=BUILD_GIT_COMMIT={}", git_commit());
+    println!(
+        "cargo:rustc-env=BUILD_TIMESTAMP={}",
+        chrono::Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true)
+    );
+    // rebuilt on new commits rather than on every compile
+    println!("cargo:rerun-if-env-changed=GIT_COMMIT");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+}
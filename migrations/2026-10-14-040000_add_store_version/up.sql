@@ -0,0 +1 @@
+ALTER TABLE stores ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
@@ -0,0 +1,2 @@
+DROP INDEX stores_idempotency_key_idx;
+ALTER TABLE stores DROP COLUMN idempotency_key;
@@ -0,0 +1,2 @@
+ALTER TABLE stores ADD COLUMN idempotency_key TEXT;
+CREATE UNIQUE INDEX stores_idempotency_key_idx ON stores (idempotency_key);
@@ -0,0 +1 @@
+DROP INDEX stores_api_id_idx;
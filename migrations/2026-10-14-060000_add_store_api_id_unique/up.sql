@@ -0,0 +1 @@
+CREATE UNIQUE INDEX stores_api_id_idx ON stores (api_id);
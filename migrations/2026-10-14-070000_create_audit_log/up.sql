@@ -0,0 +1,9 @@
+CREATE TABLE audit_log
+(
+  id SERIAL PRIMARY KEY,
+  action TEXT NOT NULL,
+  api_id TEXT NOT NULL,
+  principal TEXT,
+  created_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'utc')
+);
+CREATE INDEX audit_log_api_id_idx ON audit_log (api_id);
@@ -0,0 +1 @@
+ALTER TABLE stores DROP COLUMN content_hash;
@@ -0,0 +1,2 @@
+ALTER TABLE stores ADD COLUMN content_hash TEXT;
+CREATE INDEX stores_content_hash_idx ON stores (content_hash);
@@ -0,0 +1,6 @@
+-- not in schema.rs: diesel 1.x has no tsvector type, so queries reach it through SQL fragments
+ALTER TABLE stores
+  -- only string values are indexed, so field names do not match every document
+  ADD COLUMN search tsvector
+    GENERATED ALWAYS AS (jsonb_to_tsvector('english', data::jsonb, '["string"]')) STORED;
+CREATE INDEX stores_search_idx ON stores USING GIN (search);
@@ -0,0 +1,2 @@
+ALTER TABLE stores ADD COLUMN deleted_at TIMESTAMP;
+CREATE INDEX stores_deleted_at_idx ON stores (deleted_at) WHERE deleted_at IS NOT NULL;
@@ -0,0 +1,9 @@
+CREATE TABLE store_revisions
+(
+  id SERIAL PRIMARY KEY,
+  api_id TEXT NOT NULL,
+  data TEXT NOT NULL,
+  version INTEGER NOT NULL,
+  changed_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'utc')
+);
+CREATE INDEX store_revisions_api_id_idx ON store_revisions (api_id, id);
@@ -0,0 +1,141 @@
+use crate::config::Settings;
+use crate::request_id::REQUEST_ID_HEADER;
+use actix_service::{Service, Transform};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::Error;
+use futures::future::{ok, Ready};
+use std::future::Future;
+use std::io::{self, Write};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+/// Log target used for access log records.
+pub const ACCESS_TARGET: &str = "access";
+
+/// Access log flavour selected by `LOG_FORMAT`.
+#[derive(Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl LogFormat {
+    pub fn from_settings(settings: &Settings) -> io::Result<Self> {
+        match settings.log_format.as_deref() {
+            None | Some("text") => Ok(LogFormat::Text),
+            Some("json") => Ok(LogFormat::Json),
+            Some(other) => Err(io::Error::other(format!(
+                "invalid LOG_FORMAT={:?}: expected text or json",
+                other
+            ))),
+        }
+    }
+}
+
+/// Target of the plaintext `middleware::Logger` lines.
+const TEXT_ACCESS_TARGET: &str = "actix_web::middleware::logger";
+
+/// Install the global logger; in JSON mode every line is a single JSON object.
+///
+/// `middleware::Logger` stays wrapped in both modes (swapping it out would change
+/// the response body type), so JSON mode silences its target instead.
+pub fn init_logger(format: LogFormat) {
+    let mut filters = format!("actix_web=info,state=info,{}=info", ACCESS_TARGET);
+    if format == LogFormat::Json {
+        filters.push_str(&format!(",{}=off", TEXT_ACCESS_TARGET));
+    }
+    std::env::set_var("RUST_LOG", filters);
+
+    let mut builder = env_logger::Builder::from_default_env();
+    if format == LogFormat::Json {
+        builder.format(|buf, record| {
+            if record.target() == ACCESS_TARGET {
+                return writeln!(buf, "{}", record.args());
+            }
+            let line = json!({
+                "timestamp": chrono::Utc::now().to_rfc3339(),
+                "level": record.level().to_string(),
+                "target": record.target(),
+                "message": record.args().to_string(),
+            });
+            writeln!(buf, "{}", line)
+        });
+    }
+    builder.init();
+}
+
+/// Middleware emitting one JSON object per request under the `access` target.
+///
+/// Wrap it outside `RequestIdentifier` so the response already carries the id.
+pub struct JsonLogger;
+
+impl<S, B> Transform<S> for JsonLogger
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = JsonLoggerMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(JsonLoggerMiddleware { service })
+    }
+}
+
+pub struct JsonLoggerMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service for JsonLoggerMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let method = req.method().to_string();
+        let path = req.path().to_string();
+        let remote_addr = req.connection_info().remote().map(String::from);
+        let start = Instant::now();
+
+        let fut = self.service.call(req);
+        Box::pin(async move {
+            let res = fut.await;
+            let (status, request_id) = match &res {
+                Ok(res) => (
+                    res.status().as_u16(),
+                    res.headers()
+                        .get(REQUEST_ID_HEADER)
+                        .and_then(|v| v.to_str().ok())
+                        .map(String::from),
+                ),
+                Err(e) => (e.as_response_error().status_code().as_u16(), None),
+            };
+            let line = json!({
+                "method": method,
+                "path": path,
+                "status": status,
+                "duration_ms": start.elapsed().as_secs_f64() * 1000.0,
+                "remote_addr": remote_addr,
+                "request_id": request_id,
+            });
+            log::info!(target: ACCESS_TARGET, "{}", line);
+            res
+        })
+    }
+}
@@ -0,0 +1,116 @@
+use crate::db;
+use crate::error::ApiError;
+use crate::model::{AuditEntry, NewAuditEntry, Paginated, Pagination};
+use crate::schema::audit_log::dsl::*;
+use crate::ReadPool;
+use actix_web::{web, HttpResponse};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use serde_derive::Deserialize;
+use utoipa::IntoParams;
+
+/// Kind of mutation recorded in the audit log.
+#[derive(Clone, Copy)]
+pub enum Action {
+    Create,
+    Update,
+    Delete,
+}
+
+impl Action {
+    fn as_str(self) -> &'static str {
+        match self {
+            Action::Create => "create",
+            Action::Update => "update",
+            Action::Delete => "delete",
+        }
+    }
+}
+
+/// Record `kind` on every store in `ids`.
+///
+/// Call it inside the mutation's transaction so the change and its audit rows commit
+/// or roll back together.
+pub fn record<'a>(
+    conn: &PgConnection,
+    kind: Action,
+    ids: impl IntoIterator<Item = &'a str>,
+    who: Option<&str>,
+) -> QueryResult<()> {
+    let now = chrono::Utc::now().naive_utc();
+    let entries: Vec<NewAuditEntry> = ids
+        .into_iter()
+        .map(|target| NewAuditEntry {
+            action: kind.as_str(),
+            api_id: target,
+            principal: who,
+            created_at: now,
+        })
+        .collect();
+    diesel::insert_into(audit_log)
+        .values(&entries)
+        .execute(conn)
+        .map(|_| ())
+}
+
+/// Query parameters of `GET /v1/audit`; `limit` and `offset` behave as on the store list.
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AuditQuery {
+    /// only entries about this store
+    pub api_id: Option<String>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/audit",
+    tag = "audit",
+    params(AuditQuery),
+    responses(
+        (status = 200, description = "Audit entries, newest first", body = Paginated<AuditEntry>),
+        (status = 400, description = "Invalid query parameters", body = crate::error::ErrorBody),
+    )
+)]
+/// page through the audit trail, newest first
+pub async fn list_audit(
+    query: web::Query<AuditQuery>,
+    pool: web::Data<ReadPool>,
+) -> Result<HttpResponse, ApiError> {
+    let query = query.into_inner();
+    let pagination = Pagination {
+        limit: query.limit,
+        offset: query.offset,
+        after: None,
+    };
+    let (limit, offset) = pagination
+        .resolve()
+        .map_err(|msg| ApiError::BadRequest(msg.to_string()))?;
+    let target = query.api_id;
+    let (entries, total) = db::block(move || {
+        let filtered = || {
+            let mut entries = audit_log.into_boxed();
+            if let Some(target) = &target {
+                entries = entries.filter(api_id.eq(target.clone()));
+            }
+            entries
+        };
+        db::with_connection(&pool, |conn| {
+            let total = filtered().count().get_result::<i64>(conn)?;
+            let entries = filtered()
+                .order(id.desc())
+                .limit(limit)
+                .offset(offset)
+                .load::<AuditEntry>(conn)?;
+            Ok((entries, total))
+        })
+    })
+    .await?;
+    Ok(HttpResponse::Ok().json(Paginated {
+        data: entries,
+        total,
+        limit,
+        offset,
+    }))
+}
@@ -0,0 +1,514 @@
+use crate::config::Settings;
+use crate::error::ApiError;
+use actix_service::{Service, Transform};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::{header, HeaderValue, Method};
+use actix_web::{Error, HttpMessage, HttpRequest};
+use futures::future::{ok, Ready};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use serde_derive::Deserialize;
+use sha2::{Digest, Sha256};
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+
+/// Header carrying the client's API key.
+pub const API_KEY_HEADER: &str = "X-API-Key";
+
+/// Challenge sent with `401` when Basic auth is configured.
+const BASIC_CHALLENGE: &str = "Basic realm=\"state\"";
+
+/// Challenge sent with `401` when bearer tokens are accepted.
+const BEARER_CHALLENGE: &str = "Bearer realm=\"state\"";
+
+/// The single user accepted through `Authorization: Basic`.
+#[derive(Clone)]
+pub struct BasicCredentials {
+    user: String,
+    pass: String,
+}
+
+impl BasicCredentials {
+    pub fn new(user: &str, pass: &str) -> Self {
+        BasicCredentials {
+            user: user.to_string(),
+            pass: pass.to_string(),
+        }
+    }
+
+    /// Read `BASIC_AUTH_USER` and `BASIC_AUTH_PASS`, which must be set together.
+    pub fn from_settings(settings: &Settings) -> io::Result<Option<Self>> {
+        match (&settings.basic_auth_user, &settings.basic_auth_pass) {
+            (Some(user), Some(pass)) => Ok(Some(BasicCredentials::new(user, pass))),
+            (None, None) => Ok(None),
+            _ => Err(io::Error::other(
+                "BASIC_AUTH_USER and BASIC_AUTH_PASS must be set together",
+            )),
+        }
+    }
+
+    /// Whether an `Authorization` header value carries these credentials.
+    fn accepts(&self, authorization: &str) -> bool {
+        let mut parts = authorization.splitn(2, ' ');
+        let scheme = parts.next().unwrap_or_default();
+        let encoded = parts.next().unwrap_or_default().trim();
+        if !scheme.eq_ignore_ascii_case("basic") {
+            return false;
+        }
+        let decoded = match base64::decode(encoded) {
+            Ok(decoded) => decoded,
+            Err(_) => return false,
+        };
+        let colon = match decoded.iter().position(|&b| b == b':') {
+            Some(colon) => colon,
+            None => return false,
+        };
+        // compare both halves so a wrong user takes as long as a wrong password
+        constant_time_eq(&decoded[..colon], self.user.as_bytes())
+            & constant_time_eq(&decoded[colon + 1..], self.pass.as_bytes())
+    }
+}
+
+/// Who an authenticated request acts as, left in the request extensions for handlers.
+///
+/// `api-key:<fingerprint>`, `basic:<user>` or `token:<sub>`; API keys are identified by
+/// a hash prefix so the key itself is never recorded.
+#[derive(Clone, Debug)]
+pub struct Principal(pub String);
+
+impl Principal {
+    fn api_key(key: &str) -> Self {
+        let digest = Sha256::digest(key.as_bytes());
+        let fingerprint: String = digest[..4].iter().map(|b| format!("{:02x}", b)).collect();
+        Principal(format!("api-key:{}", fingerprint))
+    }
+
+    /// The principal of an authenticated request, `None` when auth is disabled or the
+    /// path is exempt.
+    pub fn of(req: &HttpRequest) -> Option<String> {
+        req.extensions().get::<Principal>().map(|p| p.0.clone())
+    }
+}
+
+/// Claims of a validated bearer token, left in the request extensions for handlers.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Claims {
+    pub sub: Option<String>,
+    /// space separated scopes, as in RFC 8693
+    pub scope: Option<String>,
+}
+
+impl Claims {
+    pub fn has_scope(&self, wanted: &str) -> bool {
+        self.scope
+            .as_deref()
+            .unwrap_or_default()
+            .split_whitespace()
+            .any(|granted| granted == wanted)
+    }
+}
+
+/// Checks `Authorization: Bearer` tokens signed with the HS256 `JWT_SECRET`.
+///
+/// The signature and `exp` are verified; `exp` is required.
+#[derive(Clone)]
+pub struct JwtValidator {
+    key: DecodingKey<'static>,
+    validation: Validation,
+}
+
+impl JwtValidator {
+    pub fn new(secret: &str) -> Self {
+        JwtValidator {
+            key: DecodingKey::from_secret(secret.as_bytes()).into_static(),
+            validation: Validation::new(Algorithm::HS256),
+        }
+    }
+
+    pub fn from_settings(settings: &Settings) -> Option<Self> {
+        settings.jwt_secret.as_deref().map(JwtValidator::new)
+    }
+
+    /// Claims of the token in an `Authorization` header value, `None` when it is not a
+    /// valid bearer token.
+    fn validate(&self, authorization: &str) -> Option<Claims> {
+        let mut parts = authorization.splitn(2, ' ');
+        let scheme = parts.next().unwrap_or_default();
+        let token = parts.next().unwrap_or_default().trim();
+        if !scheme.eq_ignore_ascii_case("bearer") {
+            return None;
+        }
+        jsonwebtoken::decode::<Claims>(token, &self.key, &self.validation)
+            .map(|data| data.claims)
+            .ok()
+    }
+}
+
+/// Middleware rejecting requests that present none of an allowed `X-API-Key`, the
+/// configured Basic credentials or a valid bearer token.
+///
+/// Any one mechanism grants access; with none configured the check is disabled.
+#[derive(Clone)]
+pub struct ApiKeyAuth {
+    keys: Rc<Vec<String>>,
+    basic: Option<Rc<BasicCredentials>>,
+    jwt: Option<Rc<JwtValidator>>,
+    exempt: Rc<Vec<String>>,
+}
+
+impl ApiKeyAuth {
+    pub fn new(keys: Vec<String>) -> Self {
+        ApiKeyAuth {
+            keys: Rc::new(keys),
+            basic: None,
+            jwt: None,
+            exempt: Rc::new(Vec::new()),
+        }
+    }
+
+    /// Also accept `credentials` through `Authorization: Basic`.
+    pub fn basic(mut self, credentials: Option<BasicCredentials>) -> Self {
+        self.basic = credentials.map(Rc::new);
+        self
+    }
+
+    /// Let requests for `path` through without a key.
+    pub fn exempt(mut self, path: &str) -> Self {
+        Rc::make_mut(&mut self.exempt).push(path.to_string());
+        self
+    }
+
+    /// Also accept bearer tokens checked by `validator`.
+    pub fn jwt(mut self, validator: Option<JwtValidator>) -> Self {
+        self.jwt = validator.map(Rc::new);
+        self
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.keys.is_empty() || self.basic.is_some() || self.jwt.is_some()
+    }
+
+    /// Who the request authenticates as through an allowed API key, the Basic
+    /// credentials or a valid bearer token, whose claims are then stored in the request
+    /// extensions; `None` when it presents none of them.
+    fn authenticate(&self, req: &ServiceRequest) -> Option<Principal> {
+        let key = req
+            .headers()
+            .get(API_KEY_HEADER)
+            .and_then(|value| value.to_str().ok());
+        if let Some(key) = key.filter(|key| is_authorized(&self.keys, key)) {
+            return Some(Principal::api_key(key));
+        }
+        let authorization = req
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())?;
+        if let Some(basic) = self
+            .basic
+            .as_ref()
+            .filter(|basic| basic.accepts(authorization))
+        {
+            return Some(Principal(format!("basic:{}", basic.user)));
+        }
+        let claims = self.jwt.as_ref()?.validate(authorization)?;
+        let principal = Principal(match &claims.sub {
+            Some(sub) => format!("token:{}", sub),
+            None => "token".to_string(),
+        });
+        req.extensions_mut().insert(claims);
+        Some(principal)
+    }
+}
+
+/// Compare two byte strings without short-circuiting on the first mismatch.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn is_authorized(keys: &[String], presented: &str) -> bool {
+    // check every key so the number of configured keys is not observable either
+    keys.iter().fold(false, |found, key| {
+        constant_time_eq(key.as_bytes(), presented.as_bytes()) | found
+    })
+}
+
+impl<S, B> Transform<S> for ApiKeyAuth
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = ApiKeyAuthMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(ApiKeyAuthMiddleware {
+            service,
+            auth: self.clone(),
+        })
+    }
+}
+
+pub struct ApiKeyAuthMiddleware<S> {
+    service: S,
+    auth: ApiKeyAuth,
+}
+
+impl<S, B> Service for ApiKeyAuthMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let exempt = self.auth.exempt.iter().any(|path| path == req.path());
+        if !self.auth.is_enabled() || exempt {
+            return Box::pin(self.service.call(req));
+        }
+        if let Some(principal) = self.auth.authenticate(&req) {
+            req.extensions_mut().insert(principal);
+            return Box::pin(self.service.call(req));
+        }
+        let mut res = req.error_response(ApiError::Unauthorized);
+        if self.auth.basic.is_some() {
+            // lets browsers and curl prompt for credentials
+            res.headers_mut().append(
+                header::WWW_AUTHENTICATE,
+                HeaderValue::from_static(BASIC_CHALLENGE),
+            );
+        }
+        if self.auth.jwt.is_some() {
+            res.headers_mut().append(
+                header::WWW_AUTHENTICATE,
+                HeaderValue::from_static(BEARER_CHALLENGE),
+            );
+        }
+        Box::pin(ok(res))
+    }
+}
+
+/// Middleware requiring bearer tokens to carry `read` for safe methods (`GET`, `HEAD`,
+/// `OPTIONS`) and `write` for everything else, answering `403` otherwise.
+///
+/// Requests admitted by API key or Basic credentials carry no claims and are not scoped.
+#[derive(Clone)]
+pub struct RequireScope {
+    read: Rc<String>,
+    write: Rc<String>,
+}
+
+impl RequireScope {
+    pub fn new(read: &str, write: &str) -> Self {
+        RequireScope {
+            read: Rc::new(read.to_string()),
+            write: Rc::new(write.to_string()),
+        }
+    }
+
+    fn required(&self, method: &Method) -> &str {
+        match *method {
+            Method::GET | Method::HEAD | Method::OPTIONS => &self.read,
+            _ => &self.write,
+        }
+    }
+}
+
+impl<S, B> Transform<S> for RequireScope
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RequireScopeMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RequireScopeMiddleware {
+            service,
+            scopes: self.clone(),
+        })
+    }
+}
+
+pub struct RequireScopeMiddleware<S> {
+    service: S,
+    scopes: RequireScope,
+}
+
+impl<S, B> Service for RequireScopeMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let required = self.scopes.required(req.method());
+        let granted = req
+            .extensions()
+            .get::<Claims>()
+            .is_none_or(|claims| claims.has_scope(required));
+
+        if granted {
+            Box::pin(self.service.call(req))
+        } else {
+            let err = ApiError::Forbidden(required.to_string());
+            Box::pin(ok(req.error_response(err)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ApiKeyAuth, BasicCredentials, Claims, JwtValidator, RequireScope, API_KEY_HEADER};
+    use actix_web::http::{header, StatusCode};
+    use actix_web::{test, web, App, HttpRequest, HttpResponse};
+    use jsonwebtoken::{EncodingKey, Header};
+    use serde_json::json;
+
+    #[actix_rt::test]
+    async fn basic_credentials_and_api_keys_both_grant_access() {
+        let mut app = test::init_service(
+            App::new()
+                .wrap(
+                    ApiKeyAuth::new(vec!["key".to_string()])
+                        .basic(Some(BasicCredentials::new("ops", "secret"))),
+                )
+                .route("/", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let allowed = [
+            (API_KEY_HEADER, "key".to_string()),
+            (
+                header::AUTHORIZATION.as_str(),
+                format!("Basic {}", base64::encode("ops:secret")),
+            ),
+        ];
+        for (name, value) in &allowed {
+            let req = test::TestRequest::get()
+                .header(*name, value.as_str())
+                .to_request();
+            let resp = test::call_service(&mut app, req).await;
+            assert_eq!(resp.status(), StatusCode::OK);
+        }
+
+        let req = test::TestRequest::get()
+            .header(
+                header::AUTHORIZATION,
+                format!("Basic {}", base64::encode("ops:wrong")),
+            )
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        assert!(resp.headers().contains_key(header::WWW_AUTHENTICATE));
+    }
+
+    #[actix_rt::test]
+    async fn valid_bearer_token_exposes_its_claims() {
+        async fn whoami(req: HttpRequest) -> HttpResponse {
+            let sub = req.extensions().get::<Claims>().and_then(|c| c.sub.clone());
+            HttpResponse::Ok().body(sub.unwrap_or_default())
+        }
+
+        let mut app = test::init_service(
+            App::new()
+                .wrap(ApiKeyAuth::new(Vec::new()).jwt(Some(JwtValidator::new("secret"))))
+                .route("/", web::get().to(whoami)),
+        )
+        .await;
+        let token = |exp: i64, secret: &str| {
+            let claims = json!({ "sub": "svc", "exp": exp });
+            let key = EncodingKey::from_secret(secret.as_bytes());
+            jsonwebtoken::encode(&Header::default(), &claims, &key).expect("token")
+        };
+        let now = chrono::Utc::now().timestamp();
+
+        let req = test::TestRequest::get()
+            .header(
+                header::AUTHORIZATION,
+                format!("Bearer {}", token(now + 60, "secret")),
+            )
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(test::read_body(resp).await, "svc");
+
+        for bad in &[token(now - 60, "secret"), token(now + 60, "other")] {
+            let req = test::TestRequest::get()
+                .header(header::AUTHORIZATION, format!("Bearer {}", bad))
+                .to_request();
+            let resp = test::call_service(&mut app, req).await;
+            assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn tokens_need_the_scope_for_the_method() {
+        let mut app = test::init_service(
+            App::new()
+                .wrap(RequireScope::new("stores:read", "stores:write"))
+                .wrap(ApiKeyAuth::new(vec!["key".to_string()]).jwt(Some(JwtValidator::new("s"))))
+                .route("/", web::get().to(HttpResponse::Ok))
+                .route("/", web::post().to(HttpResponse::Ok)),
+        )
+        .await;
+        let claims = json!({ "exp": chrono::Utc::now().timestamp() + 60, "scope": "stores:read" });
+        let key = EncodingKey::from_secret(b"s");
+        let token = jsonwebtoken::encode(&Header::default(), &claims, &key).expect("token");
+        let bearer = format!("Bearer {}", token);
+
+        let req = test::TestRequest::get()
+            .header(header::AUTHORIZATION, bearer.as_str())
+            .to_request();
+        assert_eq!(
+            test::call_service(&mut app, req).await.status(),
+            StatusCode::OK
+        );
+
+        let req = test::TestRequest::post()
+            .header(header::AUTHORIZATION, bearer.as_str())
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+        // API keys are not scoped
+        let req = test::TestRequest::post()
+            .header(API_KEY_HEADER, "key")
+            .to_request();
+        assert_eq!(
+            test::call_service(&mut app, req).await.status(),
+            StatusCode::OK
+        );
+    }
+}
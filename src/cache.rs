@@ -0,0 +1,116 @@
+use crate::model::Store;
+use moka::sync::Cache;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Most stores kept in memory at once.
+const MAX_ENTRIES: u64 = 10_000;
+
+/// Read-through cache of single stores keyed by `api_id`, shared by every worker.
+///
+/// Disabled when `CACHE_TTL_SECS` is unset; entries are dropped on update and delete
+/// and otherwise expire after the TTL.
+///
+/// A read that misses takes `generation()` before querying and hands it to `insert`, so
+/// a row read before a concurrent write committed is never cached after that write's
+/// invalidation.
+pub struct StoreCache {
+    inner: Option<Cache<String, Store>>,
+    /// bumped by every invalidation
+    generation: AtomicU64,
+}
+
+impl StoreCache {
+    pub fn new(ttl_secs: Option<u64>) -> Self {
+        let inner = ttl_secs.map(|ttl| {
+            Cache::builder()
+                .max_capacity(MAX_ENTRIES)
+                .time_to_live(Duration::from_secs(ttl))
+                .build()
+        });
+        StoreCache {
+            inner,
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    /// Whether `CACHE_TTL_SECS` turned the cache on.
+    pub fn is_enabled(&self) -> bool {
+        self.inner.is_some()
+    }
+
+    pub fn get(&self, key: &str) -> Option<Store> {
+        self.inner.as_ref()?.get(key)
+    }
+
+    /// Current generation, to be read before the query whose result is cached.
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    /// Cache `store`, read at `generation`, unless an invalidation has happened since.
+    pub fn insert(&self, store: &Store, generation: u64) {
+        if let Some(cache) = &self.inner {
+            if self.generation() != generation {
+                return;
+            }
+            cache.insert(store.api_id.clone(), store.clone());
+            // an invalidation that ran between the check and the insert missed the entry
+            if self.generation() != generation {
+                cache.invalidate(&store.api_id);
+            }
+        }
+    }
+
+    pub fn invalidate(&self, key: &str) {
+        if let Some(cache) = &self.inner {
+            // bumped first, so an insert that lands after the removal sees it
+            self.generation.fetch_add(1, Ordering::SeqCst);
+            cache.invalidate(key);
+        }
+    }
+
+    /// Drop every entry, for writes that touch stores by something other than `api_id`.
+    pub fn invalidate_all(&self) {
+        if let Some(cache) = &self.inner {
+            self.generation.fetch_add(1, Ordering::SeqCst);
+            cache.invalidate_all();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StoreCache;
+    use crate::model::Store;
+
+    fn store() -> Store {
+        let now = chrono::Utc::now().naive_utc();
+        Store {
+            id: 1,
+            data: "{}".to_string(),
+            api_id: "a".to_string(),
+            created_at: now,
+            updated_at: now,
+            version: 1,
+            idempotency_key: None,
+            content_hash: None,
+            deleted_at: None,
+        }
+    }
+
+    #[test]
+    fn reads_overtaken_by_an_invalidation_are_not_cached() {
+        let cache = StoreCache::new(Some(60));
+
+        // the row was read, then a write committed and invalidated before the insert
+        let generation = cache.generation();
+        cache.invalidate("a");
+        cache.insert(&store(), generation);
+        assert!(cache.get("a").is_none());
+
+        let generation = cache.generation();
+        cache.insert(&store(), generation);
+        assert!(cache.get("a").is_some());
+    }
+}
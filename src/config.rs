@@ -0,0 +1,192 @@
+use config::{Config, Environment, File, FileFormat};
+use serde::Deserializer;
+use serde_derive::Deserialize;
+use std::io;
+
+/// File read from the working directory when present.
+const CONFIG_FILE: &str = "config.toml";
+
+/// Every setting the server reads at startup.
+///
+/// Values come from `config.toml` when it exists, overridden by environment variables
+/// of the same name in upper case (`workers` / `WORKERS`). Lists may be given as TOML
+/// arrays or as comma separated strings.
+#[derive(Deserialize)]
+pub struct Settings {
+    pub database_url: Option<String>,
+    pub database_replica_url: Option<String>,
+    #[serde(default = "default_pool_size")]
+    pub database_pool_size: u32,
+    pub database_min_idle: Option<u32>,
+    #[serde(default = "default_connection_timeout")]
+    pub connection_timeout_secs: u64,
+    pub store_schema_path: Option<String>,
+    #[serde(default = "default_max_body_bytes")]
+    pub max_body_bytes: usize,
+    #[serde(default, deserialize_with = "comma_list")]
+    pub api_keys: Vec<String>,
+    pub basic_auth_user: Option<String>,
+    pub basic_auth_pass: Option<String>,
+    pub jwt_secret: Option<String>,
+    pub rate_limit_rps: Option<f64>,
+    pub rate_limit_burst: Option<f64>,
+    #[serde(default, deserialize_with = "comma_list")]
+    pub trusted_proxies: Vec<String>,
+    #[serde(default, deserialize_with = "comma_list")]
+    pub cors_allowed_origins: Vec<String>,
+    #[serde(default, deserialize_with = "comma_list")]
+    pub cors_allowed_methods: Vec<String>,
+    #[serde(default, deserialize_with = "comma_list")]
+    pub cors_allowed_headers: Vec<String>,
+    pub cors_max_age: Option<usize>,
+    pub request_timeout_ms: Option<u64>,
+    pub max_concurrent_requests: Option<usize>,
+    #[serde(default = "default_shutdown_timeout")]
+    pub shutdown_timeout_secs: u64,
+    #[serde(default = "default_bind_address", deserialize_with = "comma_list")]
+    pub bind_address: Vec<String>,
+    pub workers: Option<usize>,
+    pub tls_cert_path: Option<String>,
+    pub tls_key_path: Option<String>,
+    pub cache_ttl_secs: Option<u64>,
+    pub store_id_version: Option<String>,
+    #[serde(default, deserialize_with = "comma_list")]
+    pub webhook_urls: Vec<String>,
+    pub log_format: Option<String>,
+    pub error_format: Option<String>,
+    pub otel_exporter_otlp_endpoint: Option<String>,
+    #[serde(default = "default_otel_service_name")]
+    pub otel_service_name: String,
+    #[serde(default = "default_batch_max_size")]
+    pub batch_max_size: usize,
+    #[serde(default)]
+    pub dedupe_by_default: bool,
+    #[serde(default)]
+    pub soft_delete: bool,
+    #[serde(default = "default_soft_delete_grace_days")]
+    pub soft_delete_grace_days: u32,
+    pub retention_days: Option<u32>,
+    #[serde(default = "default_retention_interval")]
+    pub retention_interval_secs: u64,
+}
+
+fn default_pool_size() -> u32 {
+    10
+}
+
+fn default_connection_timeout() -> u64 {
+    5
+}
+
+fn default_max_body_bytes() -> usize {
+    256 * 1024
+}
+
+fn default_shutdown_timeout() -> u64 {
+    30
+}
+
+fn default_bind_address() -> Vec<String> {
+    vec!["127.0.0.1:8080".to_string()]
+}
+
+fn default_otel_service_name() -> String {
+    "state".to_string()
+}
+
+fn default_batch_max_size() -> usize {
+    1000
+}
+
+fn default_retention_interval() -> u64 {
+    3600
+}
+
+fn default_soft_delete_grace_days() -> u32 {
+    30
+}
+
+/// Accept `["a", "b"]` as well as `"a, b"`, dropping empty entries.
+fn comma_list<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum List {
+        Joined(String),
+        Items(Vec<String>),
+    }
+    let items = match <List as serde::Deserialize>::deserialize(deserializer)? {
+        List::Joined(raw) => raw.split(',').map(String::from).collect(),
+        List::Items(items) => items,
+    };
+    Ok(items
+        .iter()
+        .map(|item| item.trim())
+        .filter(|item| !item.is_empty())
+        .map(String::from)
+        .collect())
+}
+
+impl Settings {
+    /// Read `config.toml` (if any) and the environment, failing on malformed values.
+    pub fn load() -> io::Result<Self> {
+        Config::builder()
+            .add_source(File::new(CONFIG_FILE, FileFormat::Toml).required(false))
+            .add_source(Environment::default())
+            .build()
+            .and_then(Config::try_deserialize)
+            .map_err(|e| io::Error::other(format!("invalid configuration: {}", e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Settings;
+    use config::{Config, File, FileFormat};
+
+    fn parse(toml: &str, overrides: &[(&str, &str)]) -> Settings {
+        let mut builder = Config::builder().add_source(File::from_str(toml, FileFormat::Toml));
+        for (key, value) in overrides {
+            builder = builder.set_override(*key, *value).expect("valid override");
+        }
+        builder
+            .build()
+            .and_then(Config::try_deserialize)
+            .expect("valid settings")
+    }
+
+    #[test]
+    fn defaults_apply_when_nothing_is_set() {
+        let settings = parse("", &[]);
+        assert_eq!(settings.database_pool_size, 10);
+        assert_eq!(settings.bind_address, vec!["127.0.0.1:8080"]);
+        assert!(settings.api_keys.is_empty());
+        assert!(settings.workers.is_none());
+    }
+
+    #[test]
+    fn lists_accept_arrays_and_comma_separated_strings() {
+        let settings = parse(
+            r#"
+            api_keys = ["a", "b"]
+            bind_address = "0.0.0.0:80, [::]:80,"
+            "#,
+            &[],
+        );
+        assert_eq!(settings.api_keys, vec!["a", "b"]);
+        assert_eq!(settings.bind_address, vec!["0.0.0.0:80", "[::]:80"]);
+    }
+
+    #[test]
+    fn string_overrides_replace_file_values() {
+        // environment variables always arrive as strings
+        let settings = parse(
+            "workers = 2",
+            &[("workers", "8"), ("rate_limit_rps", "2.5")],
+        );
+        assert_eq!(settings.workers, Some(8));
+        assert_eq!(settings.rate_limit_rps, Some(2.5));
+    }
+}
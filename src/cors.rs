@@ -0,0 +1,74 @@
+use crate::config::Settings;
+use actix_cors::{Cors, CorsFactory};
+use actix_web::http::header::HeaderName;
+use actix_web::http::{Method, Uri};
+use std::io;
+
+/// CORS policy read from `CORS_ALLOWED_ORIGINS`, `CORS_ALLOWED_METHODS`,
+/// `CORS_ALLOWED_HEADERS` and `CORS_MAX_AGE`.
+///
+/// Each list is comma separated; an unset list (or `*`) allows everything,
+/// which keeps local development permissive.
+#[derive(Clone, Default)]
+pub struct CorsSettings {
+    origins: Vec<String>,
+    methods: Vec<Method>,
+    headers: Vec<HeaderName>,
+    max_age: Option<usize>,
+}
+
+/// Entries of a configured list other than the `*` wildcard.
+fn explicit(items: &[String]) -> impl Iterator<Item = &String> {
+    items.iter().filter(|item| *item != "*")
+}
+
+fn invalid(key: &str, value: &str) -> io::Error {
+    io::Error::other(format!("invalid entry {:?} in {}", value, key))
+}
+
+impl CorsSettings {
+    pub fn from_settings(settings: &Settings) -> io::Result<Self> {
+        let origins: Vec<String> = explicit(&settings.cors_allowed_origins).cloned().collect();
+        for origin in &origins {
+            origin
+                .parse::<Uri>()
+                .map_err(|_| invalid("CORS_ALLOWED_ORIGINS", origin))?;
+        }
+        let methods = explicit(&settings.cors_allowed_methods)
+            .map(|m| {
+                Method::from_bytes(m.to_uppercase().as_bytes())
+                    .map_err(|_| invalid("CORS_ALLOWED_METHODS", m))
+            })
+            .collect::<io::Result<_>>()?;
+        let headers = explicit(&settings.cors_allowed_headers)
+            .map(|h| {
+                HeaderName::from_bytes(h.as_bytes()).map_err(|_| invalid("CORS_ALLOWED_HEADERS", h))
+            })
+            .collect::<io::Result<_>>()?;
+        Ok(CorsSettings {
+            origins,
+            methods,
+            headers,
+            max_age: settings.cors_max_age,
+        })
+    }
+
+    /// Build the middleware; called once per worker from the app factory.
+    pub fn build(&self) -> CorsFactory {
+        // response headers that cross-origin scripts need to read
+        let mut cors = Cors::new().expose_headers(vec![crate::TOTAL_COUNT_HEADER]);
+        for origin in &self.origins {
+            cors = cors.allowed_origin(origin);
+        }
+        if !self.methods.is_empty() {
+            cors = cors.allowed_methods(self.methods.clone());
+        }
+        if !self.headers.is_empty() {
+            cors = cors.allowed_headers(self.headers.clone());
+        }
+        if let Some(max_age) = self.max_age {
+            cors = cors.max_age(max_age);
+        }
+        cors.finish()
+    }
+}
@@ -0,0 +1,123 @@
+use crate::config::Settings;
+use crate::error::ApiError;
+use crate::model::Store;
+use crate::schema::stores::dsl::*;
+use actix_web::{web, HttpRequest};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::sql_types::Text;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+/// Header turning content dedupe on or off for a single create.
+pub const DEDUPE_HEADER: &str = "X-Dedupe";
+
+/// Whether `POST /v1/store` returns an existing store with identical data instead of
+/// inserting a copy, when the request does not say.
+///
+/// Off unless `DEDUPE_BY_DEFAULT` is set.
+#[derive(Clone, Copy)]
+pub struct Dedupe {
+    default: bool,
+}
+
+impl Dedupe {
+    pub fn new(default: bool) -> Self {
+        Dedupe { default }
+    }
+
+    pub fn from_settings(settings: &Settings) -> Self {
+        Dedupe::new(settings.dedupe_by_default)
+    }
+}
+
+/// Dedupe choice of one request: its `X-Dedupe: true|false` header, else the app's
+/// [`Dedupe`] default.
+pub fn requested(req: &HttpRequest) -> Result<bool, ApiError> {
+    let raw = match req.headers().get(DEDUPE_HEADER) {
+        Some(raw) => raw,
+        None => {
+            return Ok(req
+                .app_data::<web::Data<Dedupe>>()
+                .is_some_and(|dedupe| dedupe.default))
+        }
+    };
+    match raw.to_str().map(str::to_ascii_lowercase).as_deref() {
+        Ok("true") => Ok(true),
+        Ok("false") => Ok(false),
+        _ => Err(ApiError::BadRequest(format!(
+            "{} must be true or false",
+            DEDUPE_HEADER
+        ))),
+    }
+}
+
+/// Append `value` as JSON with object keys sorted at every level.
+fn write_canonical(value: &Value, out: &mut String) {
+    match value {
+        Value::Object(map) => {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            out.push('{');
+            for (i, (key, item)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::String(key.clone()).to_string());
+                out.push(':');
+                write_canonical(item, out);
+            }
+            out.push('}');
+        }
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(item, out);
+            }
+            out.push(']');
+        }
+        scalar => out.push_str(&scalar.to_string()),
+    }
+}
+
+/// Hex SHA-256 of the canonical form of `document`, so key order never changes it.
+pub fn content_hash_of(document: &Value) -> String {
+    let mut canonical = String::new();
+    write_canonical(document, &mut canonical);
+    Sha256::digest(canonical.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// The oldest live store whose data hashes to `hash`.
+///
+/// Takes a transaction scoped advisory lock on the hash first, so concurrent creates of
+/// the same content queue up behind each other instead of both inserting.
+pub fn find_copy(conn: &PgConnection, hash: &str) -> QueryResult<Option<Store>> {
+    diesel::sql_query("SELECT pg_advisory_xact_lock(hashtext($1))")
+        .bind::<Text, _>(hash)
+        .execute(conn)?;
+    stores
+        .filter(content_hash.eq(hash))
+        .filter(deleted_at.is_null())
+        .order(id.asc())
+        .first::<Store>(conn)
+        .optional()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::content_hash_of;
+
+    #[test]
+    fn key_order_does_not_change_the_hash() {
+        let a = json!({ "a": 1, "b": { "x": [1, { "k": true, "j": null }], "y": "z" } });
+        let b = json!({ "b": { "y": "z", "x": [1, { "j": null, "k": true }] }, "a": 1 });
+        assert_eq!(content_hash_of(&a), content_hash_of(&b));
+        assert_ne!(content_hash_of(&a), content_hash_of(&json!({ "a": 2 })));
+    }
+}
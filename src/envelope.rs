@@ -0,0 +1,173 @@
+use crate::error::{error_body, ErrorFormat, PROBLEM_CONTENT_TYPE};
+use actix_service::{Service, Transform};
+use actix_web::dev::{ResponseBody, ServiceRequest, ServiceResponse};
+use actix_web::http::{header, HeaderMap, StatusCode};
+use actix_web::{Error, HttpResponse};
+use futures::future::{ok, Ready};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Whether the response already carries a JSON (or problem+json) body.
+fn has_json_body(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| {
+            value.starts_with("application/json") || value.starts_with(PROBLEM_CONTENT_TYPE)
+        })
+        .unwrap_or(false)
+}
+
+/// `code` for an error actix answered by itself: the reason phrase in snake case,
+/// `method_not_allowed` for a 405.
+fn status_code_name(status: StatusCode) -> String {
+    status
+        .canonical_reason()
+        .unwrap_or("error")
+        .to_ascii_lowercase()
+        .replace([' ', '-'], "_")
+        .replace('\'', "")
+}
+
+/// Middleware giving error responses that did not come from an `ApiError` (unknown
+/// routes, disallowed methods, unreadable bodies) the usual `ERROR_FORMAT` body.
+///
+/// Error responses that already carry JSON are passed through untouched.
+pub struct ErrorEnvelope;
+
+impl<S, B> Transform<S> for ErrorEnvelope
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = ErrorEnvelopeMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(ErrorEnvelopeMiddleware { service })
+    }
+}
+
+pub struct ErrorEnvelopeMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service for ErrorEnvelopeMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let fut = self.service.call(req);
+        Box::pin(async move {
+            let res = fut.await?;
+            let status = res.status();
+            let failed = status.is_client_error() || status.is_server_error();
+            if !failed || has_json_body(res.headers()) {
+                return Ok(res);
+            }
+            // keep actix's own explanation, such as the query deserialization error
+            let message = match res.response().error() {
+                Some(err) => err.to_string(),
+                None => status
+                    .canonical_reason()
+                    .unwrap_or("error")
+                    .to_ascii_lowercase(),
+            };
+            let mut replacement = error_body(
+                HttpResponse::build(status),
+                ErrorFormat::current(),
+                status,
+                &status_code_name(status),
+                message,
+                None,
+            );
+            let content_type = replacement.headers().get(header::CONTENT_TYPE).cloned();
+            let body = match replacement.take_body() {
+                ResponseBody::Body(body) | ResponseBody::Other(body) => body,
+            };
+            Ok(res.map_body(move |head, _| {
+                head.headers.remove(header::CONTENT_LENGTH);
+                if let Some(content_type) = content_type {
+                    head.headers.insert(header::CONTENT_TYPE, content_type);
+                }
+                ResponseBody::Other(body)
+            }))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{status_code_name, ErrorEnvelope};
+    use actix_web::http::StatusCode;
+    use actix_web::{test, web, App, HttpResponse};
+    use serde_json::Value;
+
+    async fn ok() -> HttpResponse {
+        HttpResponse::Ok().finish()
+    }
+
+    #[actix_rt::test]
+    async fn unrouted_errors_get_a_json_body() {
+        let mut app = test::init_service(
+            App::new()
+                .wrap(ErrorEnvelope)
+                .service(web::resource("/store").route(web::get().to(ok))),
+        )
+        .await;
+        let cases = [
+            (
+                test::TestRequest::get().uri("/nowhere"),
+                StatusCode::NOT_FOUND,
+                "not_found",
+            ),
+            (
+                test::TestRequest::delete().uri("/store"),
+                StatusCode::METHOD_NOT_ALLOWED,
+                "method_not_allowed",
+            ),
+        ];
+        for (req, status, code) in cases {
+            let resp = test::call_service(&mut app, req.to_request()).await;
+            assert_eq!(resp.status(), status);
+            let body: Value =
+                serde_json::from_slice(&test::read_body(resp).await).expect("JSON error body");
+            assert_eq!(body["code"], code);
+            assert!(body["error"].is_string());
+        }
+
+        let resp = test::call_service(
+            &mut app,
+            test::TestRequest::get().uri("/store").to_request(),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn codes_follow_the_reason_phrase() {
+        assert_eq!(status_code_name(StatusCode::NOT_FOUND), "not_found");
+        assert_eq!(
+            status_code_name(StatusCode::METHOD_NOT_ALLOWED),
+            "method_not_allowed"
+        );
+        assert_eq!(status_code_name(StatusCode::URI_TOO_LONG), "uri_too_long");
+    }
+}
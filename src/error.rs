@@ -0,0 +1,348 @@
+use crate::config::Settings;
+use actix_web::dev::HttpResponseBuilder;
+use actix_web::error::{BlockingError, InternalError, JsonPayloadError, QueryPayloadError};
+use actix_web::http::{header, StatusCode};
+use actix_web::{HttpRequest, HttpResponse, ResponseError};
+use diesel::r2d2::PoolError;
+use serde_derive::Serialize;
+use std::fmt;
+use std::io;
+use std::sync::OnceLock;
+use utoipa::ToSchema;
+
+/// Seconds clients are told to back off when no pooled connection was free.
+const POOL_RETRY_AFTER_SECS: u64 = 1;
+
+/// Media type of RFC 7807 error bodies.
+pub const PROBLEM_CONTENT_TYPE: &str = "application/problem+json";
+
+/// Shape of error bodies selected by `ERROR_FORMAT`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ErrorFormat {
+    /// `{"error": "...", "code": "..."}`
+    Flat,
+    /// RFC 7807 `application/problem+json`
+    Problem,
+}
+
+impl ErrorFormat {
+    pub fn from_settings(settings: &Settings) -> io::Result<Self> {
+        match settings.error_format.as_deref() {
+            None | Some("flat") => Ok(ErrorFormat::Flat),
+            Some("problem") => Ok(ErrorFormat::Problem),
+            Some(other) => Err(io::Error::other(format!(
+                "invalid ERROR_FORMAT={:?}: expected flat or problem",
+                other
+            ))),
+        }
+    }
+
+    /// Use `self` for every error response from now on; only the first call counts.
+    pub fn install(self) {
+        let _ = ERROR_FORMAT.set(self);
+    }
+
+    /// The installed format, `Flat` until one is installed.
+    pub fn current() -> Self {
+        ERROR_FORMAT.get().copied().unwrap_or(ErrorFormat::Flat)
+    }
+}
+
+/// `ResponseError::error_response` has no access to app data, so the format is global.
+static ERROR_FORMAT: OnceLock<ErrorFormat> = OnceLock::new();
+
+/// JSON body of every error response in the flat format.
+#[derive(Serialize, ToSchema)]
+pub struct ErrorBody {
+    pub error: String,
+    /// Stable machine-readable reason, such as `not_found` or `validation_failed`.
+    pub code: String,
+    /// Individual schema violations, only present on `422`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<Vec<String>>,
+}
+
+/// RFC 7807 body of every error response with `ERROR_FORMAT=problem`.
+#[derive(Serialize, ToSchema)]
+pub struct ProblemDetails {
+    /// always `about:blank`: the status and `code` identify the problem
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// reason phrase of the status
+    pub title: String,
+    pub status: u16,
+    pub detail: String,
+    pub code: String,
+    /// Individual schema violations, only present on `422`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub errors: Option<Vec<String>>,
+}
+
+/// Finish `resp` with an error body in `format`.
+pub fn error_body(
+    mut resp: HttpResponseBuilder,
+    format: ErrorFormat,
+    status: StatusCode,
+    code: &str,
+    message: String,
+    details: Option<Vec<String>>,
+) -> HttpResponse {
+    match format {
+        ErrorFormat::Flat => resp.json(ErrorBody {
+            error: message,
+            code: code.to_string(),
+            details,
+        }),
+        ErrorFormat::Problem => resp
+            .content_type(PROBLEM_CONTENT_TYPE)
+            .json(ProblemDetails {
+                kind: "about:blank".to_string(),
+                title: status.canonical_reason().unwrap_or("Error").to_string(),
+                status: status.as_u16(),
+                detail: message,
+                code: code.to_string(),
+                errors: details,
+            }),
+    }
+}
+
+/// Errors surfaced by the store handlers.
+///
+/// Every variant renders as an error body in the `ERROR_FORMAT` shape, carrying a
+/// matching status code and a stable `code`.
+#[derive(Debug)]
+pub enum ApiError {
+    NotFound,
+    /// no pooled connection became free within `CONNECTION_TIMEOUT_SECS`
+    DbPool(PoolError),
+    DbQuery(diesel::result::Error),
+    BadRequest(String),
+    Conflict(String),
+    Validation(Vec<String>),
+    PayloadTooLarge,
+    UnsupportedMediaType,
+    Unauthorized,
+    /// authenticated, but the token lacks the named scope
+    Forbidden(String),
+    RateLimited(u64),
+    Timeout,
+    /// every `MAX_CONCURRENT_REQUESTS` permit is taken
+    Overloaded,
+    Internal,
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::NotFound => write!(f, "store not found"),
+            ApiError::DbPool(_) => write!(f, "database_busy"),
+            ApiError::DbQuery(_) => write!(f, "database error"),
+            ApiError::BadRequest(msg) | ApiError::Conflict(msg) => write!(f, "{}", msg),
+            ApiError::Validation(_) => write!(f, "validation failed"),
+            ApiError::PayloadTooLarge => write!(f, "request body too large"),
+            ApiError::UnsupportedMediaType => write!(f, "unsupported content type"),
+            ApiError::Unauthorized => write!(f, "unauthorized"),
+            ApiError::Forbidden(scope) => write!(f, "missing scope {}", scope),
+            ApiError::RateLimited(_) => write!(f, "too many requests"),
+            ApiError::Timeout => write!(f, "request timed out"),
+            ApiError::Overloaded => write!(f, "overloaded"),
+            ApiError::Internal => write!(f, "internal error"),
+        }
+    }
+}
+
+impl ApiError {
+    /// Stable machine-readable name of the error, sent as `code`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::NotFound => "not_found",
+            ApiError::DbPool(_) => "database_busy",
+            ApiError::DbQuery(_) => "database_error",
+            ApiError::BadRequest(_) => "bad_request",
+            ApiError::Conflict(_) => "conflict",
+            ApiError::Validation(_) => "validation_failed",
+            ApiError::PayloadTooLarge => "payload_too_large",
+            ApiError::UnsupportedMediaType => "unsupported_media_type",
+            ApiError::Unauthorized => "unauthorized",
+            ApiError::Forbidden(_) => "forbidden",
+            ApiError::RateLimited(_) => "rate_limited",
+            ApiError::Timeout => "timeout",
+            ApiError::Overloaded => "overloaded",
+            ApiError::Internal => "internal",
+        }
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::NotFound => StatusCode::NOT_FOUND,
+            ApiError::DbPool(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::DbQuery(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiError::UnsupportedMediaType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ApiError::Unauthorized => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::Timeout => StatusCode::GATEWAY_TIMEOUT,
+            ApiError::Overloaded => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        self.render(ErrorFormat::current())
+    }
+}
+
+impl ApiError {
+    /// The response to this error with its body in `format`.
+    pub fn render(&self, format: ErrorFormat) -> HttpResponse {
+        let mut resp = HttpResponse::build(self.status_code());
+        let mut details = None;
+        match self {
+            ApiError::DbQuery(err) => log::error!("database query failed: {}", err),
+            ApiError::Validation(violations) => details = Some(violations.clone()),
+            ApiError::DbPool(err) => {
+                log::warn!(
+                    "connection timeout: no database connection became free within CONNECTION_TIMEOUT_SECS ({})",
+                    err
+                );
+                resp.header(header::RETRY_AFTER, POOL_RETRY_AFTER_SECS.to_string());
+            }
+            ApiError::RateLimited(retry_after) => {
+                resp.header(header::RETRY_AFTER, retry_after.to_string());
+            }
+            _ => {}
+        }
+        error_body(
+            resp,
+            format,
+            self.status_code(),
+            self.code(),
+            self.to_string(),
+            details,
+        )
+    }
+}
+
+/// r2d2 only fails `get()` once `connection_timeout` elapses without a free connection.
+impl From<PoolError> for ApiError {
+    fn from(err: PoolError) -> Self {
+        ApiError::DbPool(err)
+    }
+}
+
+impl From<diesel::result::Error> for ApiError {
+    fn from(err: diesel::result::Error) -> Self {
+        match err {
+            diesel::result::Error::NotFound => ApiError::NotFound,
+            err => ApiError::DbQuery(err),
+        }
+    }
+}
+
+impl From<BlockingError<ApiError>> for ApiError {
+    fn from(err: BlockingError<ApiError>) -> Self {
+        match err {
+            BlockingError::Error(err) => err,
+            BlockingError::Canceled => ApiError::Internal,
+        }
+    }
+}
+
+/// `JsonConfig` error handler answering oversized bodies with a 413, bodies that do not
+/// parse into the expected type with a 400 and other content types with a 415.
+pub fn json_error_handler(err: JsonPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    match err {
+        JsonPayloadError::Overflow => {
+            let resp = ApiError::PayloadTooLarge.error_response();
+            InternalError::from_response(err, resp).into()
+        }
+        // broken syntax as well as valid JSON of the wrong shape
+        JsonPayloadError::Deserialize(ref cause) => {
+            let resp = ApiError::BadRequest(format!("invalid JSON: {}", cause)).error_response();
+            InternalError::from_response(err, resp).into()
+        }
+        JsonPayloadError::ContentType => {
+            let resp = ApiError::UnsupportedMediaType.error_response();
+            InternalError::from_response(err, resp).into()
+        }
+        JsonPayloadError::Payload(ref cause) => {
+            let resp = ApiError::BadRequest(format!("could not read the body: {}", cause))
+                .error_response();
+            InternalError::from_response(err, resp).into()
+        }
+    }
+}
+
+/// `QueryConfig` error handler answering query strings that do not fit the handler's
+/// parameters with a 400 in the usual error shape.
+pub fn query_error_handler(err: QueryPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    let resp = match &err {
+        QueryPayloadError::Deserialize(cause) => {
+            ApiError::BadRequest(format!("invalid query string: {}", cause)).error_response()
+        }
+    };
+    InternalError::from_response(err, resp).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ApiError, ErrorFormat, PROBLEM_CONTENT_TYPE};
+    use actix_web::http::{header, StatusCode};
+    use actix_web::HttpResponse;
+    use serde_json::Value;
+
+    fn body_of(resp: &mut HttpResponse) -> Value {
+        match resp.take_body() {
+            actix_web::dev::ResponseBody::Body(actix_web::dev::Body::Bytes(bytes)) => {
+                serde_json::from_slice(&bytes).expect("JSON error body")
+            }
+            _ => panic!("error body is not a single chunk"),
+        }
+    }
+
+    #[test]
+    fn flat_errors_carry_a_code() {
+        let mut resp = ApiError::NotFound.render(ErrorFormat::Flat);
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            resp.headers()
+                .get(header::CONTENT_TYPE)
+                .expect("content type"),
+            "application/json"
+        );
+        assert_eq!(
+            body_of(&mut resp),
+            json!({ "error": "store not found", "code": "not_found" })
+        );
+    }
+
+    #[test]
+    fn problem_errors_follow_rfc_7807() {
+        let err = ApiError::Validation(vec!["n: not a string".to_string()]);
+        let mut resp = err.render(ErrorFormat::Problem);
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            resp.headers()
+                .get(header::CONTENT_TYPE)
+                .expect("content type"),
+            PROBLEM_CONTENT_TYPE
+        );
+        assert_eq!(
+            body_of(&mut resp),
+            json!({
+                "type": "about:blank",
+                "title": "Unprocessable Entity",
+                "status": 422,
+                "detail": "validation failed",
+                "code": "validation_failed",
+                "errors": ["n: not a string"],
+            })
+        );
+    }
+}
@@ -0,0 +1,117 @@
+use crate::error::ApiError;
+use crate::model::StoreResponse;
+use actix_web::dev::BodyEncoding;
+use actix_web::http::{header, ContentEncoding};
+use actix_web::{web, HttpResponse};
+use futures::stream;
+use serde_json::Value;
+use tokio::sync::broadcast::{self, Receiver, RecvError, Sender};
+
+/// Events buffered per subscriber before it is considered too slow and dropped.
+const CHANNEL_CAPACITY: usize = 256;
+
+/// A change to one store, encoded once for every kind of subscriber.
+#[derive(Clone)]
+pub struct StoreEvent {
+    pub api_id: String,
+    /// SSE frame carrying the store, only present for created stores
+    pub frame: Option<web::Bytes>,
+    /// `{"event": "created" | "updated" | "deleted", "api_id": ..., "store": ...}`
+    pub message: String,
+}
+
+/// Fan-out of store events to `GET /v1/store/stream` and `/v1/store/ws` subscribers,
+/// shared by every worker.
+pub struct StoreEvents {
+    sender: Sender<StoreEvent>,
+}
+
+impl StoreEvents {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+        StoreEvents { sender }
+    }
+
+    pub fn subscribe(&self) -> Receiver<StoreEvent> {
+        self.sender.subscribe()
+    }
+
+    pub fn created(&self, store: &StoreResponse) {
+        self.publish("created", &store.api_id, Some(store));
+    }
+
+    pub fn updated(&self, store: &StoreResponse) {
+        self.publish("updated", &store.api_id, Some(store));
+    }
+
+    pub fn deleted(&self, api_id: &str) {
+        self.publish("deleted", api_id, None);
+    }
+
+    /// Encode the event once and hand it to every current subscriber.
+    ///
+    /// Never blocks; having no subscribers is not an error.
+    fn publish(&self, kind: &str, api_id: &str, store: Option<&StoreResponse>) {
+        let store = match store.map(serde_json::to_value).transpose() {
+            Ok(store) => store,
+            Err(e) => return log::error!("could not serialize store event: {}", e),
+        };
+        let frame = match (kind, &store) {
+            ("created", Some(store)) => Some(web::Bytes::from(format!("data: {}\n\n", store))),
+            _ => None,
+        };
+        let message = json!({
+            "event": kind,
+            "api_id": api_id,
+            "store": store.unwrap_or(Value::Null),
+        });
+        let _ = self.sender.send(StoreEvent {
+            api_id: api_id.to_string(),
+            frame,
+            message: message.to_string(),
+        });
+    }
+}
+
+impl Default for StoreEvents {
+    fn default() -> Self {
+        StoreEvents::new()
+    }
+}
+
+/// Next event for a subscriber, ending the stream once it falls behind the channel.
+pub async fn next_event(
+    mut receiver: Receiver<StoreEvent>,
+) -> Option<(StoreEvent, Receiver<StoreEvent>)> {
+    match receiver.recv().await {
+        Ok(event) => Some((event, receiver)),
+        Err(RecvError::Lagged(missed)) => {
+            log::warn!("dropping store event subscriber {} events behind", missed);
+            None
+        }
+        Err(RecvError::Closed) => None,
+    }
+}
+
+/// Next SSE frame, skipping updates and deletes which the stream does not carry.
+async fn next_frame(
+    mut receiver: Receiver<StoreEvent>,
+) -> Option<(Result<web::Bytes, ApiError>, Receiver<StoreEvent>)> {
+    loop {
+        let (event, rest) = next_event(receiver).await?;
+        match event.frame {
+            Some(frame) => return Some((Ok(frame), rest)),
+            None => receiver = rest,
+        }
+    }
+}
+
+/// `GET /v1/store/stream`, a `text/event-stream` of stores as they are created.
+pub async fn stream_stores(events: web::Data<StoreEvents>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .header(header::CACHE_CONTROL, "no-cache")
+        // compressing would hold frames back until the encoder's buffer fills
+        .encoding(ContentEncoding::Identity)
+        .streaming(stream::unfold(events.subscribe(), next_frame))
+}
@@ -0,0 +1,95 @@
+use crate::db;
+use crate::error::ApiError;
+use crate::model::Store;
+use crate::schema::stores::dsl::*;
+use crate::ReadPool;
+use actix_web::http::header;
+use actix_web::{web, HttpResponse};
+use diesel::prelude::*;
+use futures::stream;
+
+/// Rows fetched from the database per streamed chunk.
+const CHUNK_SIZE: i64 = 1000;
+
+struct Cursor {
+    pool: web::Data<ReadPool>,
+    after: i32,
+    header_written: bool,
+    done: bool,
+}
+
+fn encode(rows: &[Store], with_header: bool) -> Result<Vec<u8>, csv::Error> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    if with_header {
+        writer.write_record(["api_id", "data", "created_at", "updated_at"])?;
+    }
+    for row in rows {
+        writer.write_record([
+            row.api_id.as_str(),
+            row.data.as_str(),
+            &row.created_at.to_string(),
+            &row.updated_at.to_string(),
+        ])?;
+    }
+    writer
+        .into_inner()
+        .map_err(|e| csv::Error::from(e.into_error()))
+}
+
+/// Load and encode the chunk following `cursor`, or `None` once the table is exhausted.
+async fn next_chunk(mut cursor: Cursor) -> Option<(Result<web::Bytes, ApiError>, Cursor)> {
+    if cursor.done {
+        return None;
+    }
+    let pool = cursor.pool.clone();
+    let after = cursor.after;
+    let rows = db::block(move || {
+        db::with_connection(&pool, |conn| {
+            Ok(stores
+                .filter(id.gt(after))
+                .filter(deleted_at.is_null())
+                .order(id.asc())
+                .limit(CHUNK_SIZE)
+                .load::<Store>(conn)?)
+        })
+    })
+    .await;
+
+    let rows = match rows {
+        Ok(rows) => rows,
+        Err(e) => {
+            cursor.done = true;
+            return Some((Err(e.into()), cursor));
+        }
+    };
+    if rows.is_empty() && cursor.header_written {
+        return None;
+    }
+    cursor.done = (rows.len() as i64) < CHUNK_SIZE;
+    if let Some(last) = rows.last() {
+        cursor.after = last.id;
+    }
+    let chunk = encode(&rows, !cursor.header_written).map_err(|e| {
+        log::error!("failed to encode csv: {}", e);
+        ApiError::Internal
+    });
+    cursor.header_written = true;
+    Some((chunk.map(web::Bytes::from), cursor))
+}
+
+/// `GET /v1/store/export.csv`, streamed in id order without buffering the whole table.
+pub async fn export_csv(pool: web::Data<ReadPool>) -> HttpResponse {
+    let cursor = Cursor {
+        pool,
+        after: 0,
+        header_written: false,
+        done: false,
+    };
+    HttpResponse::Ok()
+        .content_type("text/csv")
+        .header(
+            header::CONTENT_DISPOSITION,
+            "attachment; filename=\"stores.csv\"",
+        )
+        .streaming(stream::unfold(cursor, next_chunk))
+}
@@ -0,0 +1,151 @@
+use crate::audit::{self, Action};
+use crate::auth::Principal;
+use crate::db;
+use crate::dedupe;
+use crate::error::ApiError;
+use crate::model::NewStore;
+use crate::schema::stores::dsl::*;
+use crate::store_id::IdVersion;
+use crate::validation::{require_object, StoreSchema};
+use crate::{Limits, Pool};
+use actix_web::{web, HttpRequest, HttpResponse};
+use diesel::prelude::*;
+use futures::StreamExt;
+use serde_json::Value;
+
+/// Rows inserted per transaction.
+const CHUNK_SIZE: usize = 500;
+
+/// Insert one chunk of serialized documents atomically, returning the row count.
+async fn insert_chunk(
+    pool: &web::Data<Pool>,
+    id_version: IdVersion,
+    documents: Vec<(String, String)>,
+    principal: Option<String>,
+) -> Result<usize, ApiError> {
+    let pool = pool.clone();
+    db::block(move || -> Result<_, ApiError> {
+        let now = chrono::Utc::now().naive_utc();
+        let ids: Vec<String> = documents.iter().map(|_| id_version.generate()).collect();
+        let new_entries: Vec<NewStore> = documents
+            .iter()
+            .zip(&ids)
+            .map(|((serialized, hash), uuid)| NewStore {
+                data: serialized,
+                api_id: uuid,
+                created_at: now,
+                updated_at: now,
+                idempotency_key: None,
+                content_hash: Some(hash),
+            })
+            .collect();
+        db::with_transaction(&pool, |conn| {
+            let recorded = ids.iter().map(String::as_str);
+            audit::record(conn, Action::Create, recorded, principal.as_deref())?;
+            Ok(diesel::insert_into(stores)
+                .values(&new_entries)
+                .execute(conn)?)
+        })
+    })
+    .await
+    .map_err(ApiError::from)
+}
+
+/// Accumulates parsed lines and flushes them to the database in chunks.
+struct Importer<'a> {
+    pool: &'a web::Data<Pool>,
+    schema: &'a StoreSchema,
+    id_version: IdVersion,
+    principal: Option<String>,
+    line_no: usize,
+    /// serialized documents with their content hash
+    pending: Vec<(String, String)>,
+    inserted: usize,
+    errors: Vec<Value>,
+}
+
+impl Importer<'_> {
+    async fn push_line(&mut self, line: &[u8]) -> Result<(), ApiError> {
+        self.line_no += 1;
+        if line.iter().all(u8::is_ascii_whitespace) {
+            return Ok(());
+        }
+        let parsed = serde_json::from_slice::<Value>(line)
+            .map_err(|e| e.to_string())
+            .and_then(|document| {
+                require_object(&document)
+                    .and_then(|_| self.schema.validate(&document))
+                    .map(|_| document)
+                    .map_err(|e| e.to_string())
+            });
+        match parsed {
+            Ok(document) => {
+                let hash = dedupe::content_hash_of(&document);
+                self.pending.push((document.to_string(), hash));
+                if self.pending.len() >= CHUNK_SIZE {
+                    self.flush().await?;
+                }
+            }
+            Err(error) => self
+                .errors
+                .push(json!({ "line": self.line_no, "error": error })),
+        }
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), ApiError> {
+        if !self.pending.is_empty() {
+            let chunk = std::mem::take(&mut self.pending);
+            let principal = self.principal.clone();
+            self.inserted += insert_chunk(self.pool, self.id_version, chunk, principal).await?;
+        }
+        Ok(())
+    }
+}
+
+/// `POST /v1/store/import` with a newline-delimited JSON body, one store per line.
+///
+/// The body is streamed; valid lines are inserted in transactions of 500 rows and
+/// lines that fail to parse are reported by line number instead of aborting the import.
+pub async fn import_ndjson(
+    req: HttpRequest,
+    mut payload: web::Payload,
+    pool: web::Data<Pool>,
+    schema: web::Data<StoreSchema>,
+    limits: web::Data<Limits>,
+    id_version: web::Data<IdVersion>,
+) -> Result<HttpResponse, ApiError> {
+    let mut importer = Importer {
+        pool: &pool,
+        schema: &schema,
+        id_version: **id_version,
+        principal: Principal::of(&req),
+        line_no: 0,
+        pending: Vec::new(),
+        inserted: 0,
+        errors: Vec::new(),
+    };
+    let mut buffer: Vec<u8> = Vec::new();
+
+    while let Some(chunk) = payload.next().await {
+        let chunk =
+            chunk.map_err(|e| ApiError::BadRequest(format!("could not read body: {}", e)))?;
+        buffer.extend_from_slice(&chunk);
+        while let Some(pos) = buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=pos).collect();
+            importer.push_line(&line).await?;
+        }
+        if buffer.len() > limits.max_line_bytes {
+            return Err(ApiError::PayloadTooLarge);
+        }
+    }
+    if !buffer.is_empty() {
+        importer.push_line(&buffer).await?;
+    }
+    importer.flush().await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "inserted": importer.inserted,
+        "errors": importer.errors,
+    })))
+}
@@ -0,0 +1,144 @@
+use crate::error::ApiError;
+use actix_service::{Service, Transform};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::Error;
+use futures::future::{ok, Ready};
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::sync::Semaphore;
+
+/// Middleware answering `503 overloaded` straight away when every permit is taken.
+///
+/// The semaphore is shared by all workers, so `MAX_CONCURRENT_REQUESTS` bounds the whole
+/// server. A permit is held until the response head is produced; streamed bodies and
+/// WebSocket sessions do not keep one for their lifetime.
+#[derive(Clone)]
+pub struct ConcurrencyLimit {
+    permits: Arc<Semaphore>,
+    exempt: Rc<Vec<String>>,
+}
+
+impl ConcurrencyLimit {
+    pub fn new(permits: Arc<Semaphore>) -> Self {
+        ConcurrencyLimit {
+            permits,
+            exempt: Rc::new(Vec::new()),
+        }
+    }
+
+    /// Let requests for `path` through without taking a permit.
+    pub fn exempt(mut self, path: &str) -> Self {
+        Rc::make_mut(&mut self.exempt).push(path.to_string());
+        self
+    }
+}
+
+impl<S, B> Transform<S> for ConcurrencyLimit
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = ConcurrencyLimitMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(ConcurrencyLimitMiddleware {
+            service,
+            limit: self.clone(),
+        })
+    }
+}
+
+pub struct ConcurrencyLimitMiddleware<S> {
+    service: S,
+    limit: ConcurrencyLimit,
+}
+
+impl<S, B> Service for ConcurrencyLimitMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        if self.limit.exempt.iter().any(|path| path == req.path()) {
+            return Box::pin(self.service.call(req));
+        }
+        let permit = match self.limit.permits.clone().try_acquire_owned() {
+            Ok(permit) => permit,
+            // a response rather than an `Err`, so the access log, request id and CORS
+            // headers still apply to it
+            Err(_) => return Box::pin(ok(req.error_response(ApiError::Overloaded))),
+        };
+        let fut = self.service.call(req);
+        Box::pin(async move {
+            let res = fut.await;
+            drop(permit);
+            res
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ConcurrencyLimit;
+    use actix_web::http::StatusCode;
+    use actix_web::{test, web, App, HttpResponse};
+    use std::sync::Arc;
+    use tokio::sync::Semaphore;
+
+    async fn ok() -> HttpResponse {
+        HttpResponse::Ok().finish()
+    }
+
+    #[actix_rt::test]
+    async fn requests_beyond_the_limit_get_503_unless_exempt() {
+        let permits = Arc::new(Semaphore::new(1));
+        let mut app = test::init_service(
+            App::new()
+                .wrap(ConcurrencyLimit::new(permits.clone()).exempt("/health"))
+                .route("/work", web::get().to(ok))
+                .route("/health", web::get().to(ok)),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/work").to_request();
+        assert_eq!(
+            test::call_service(&mut app, req).await.status(),
+            StatusCode::OK
+        );
+
+        // another request still in flight holds the only permit
+        let held = permits
+            .clone()
+            .try_acquire_owned()
+            .expect("permit is free again");
+        let req = test::TestRequest::get().uri("/work").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let req = test::TestRequest::get().uri("/health").to_request();
+        assert_eq!(
+            test::call_service(&mut app, req).await.status(),
+            StatusCode::OK
+        );
+        drop(held);
+    }
+}
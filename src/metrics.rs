@@ -0,0 +1,343 @@
+use crate::error::ApiError;
+use actix_service::{Service, ServiceFactory, Transform};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::{web, Error, HttpMessage, HttpRequest, HttpResponse, Resource, ResponseError};
+use futures::future::{ok, Ready};
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+/// Prometheus collectors shared by every worker.
+pub struct Metrics {
+    registry: Registry,
+    requests_total: IntCounterVec,
+    request_duration: HistogramVec,
+    in_flight: IntGaugeVec,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let requests_total = IntCounterVec::new(
+            Opts::new("http_requests_total", "Total number of HTTP requests"),
+            &["endpoint", "method", "status"],
+        )
+        .expect("valid metric definition");
+        let request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "HTTP request latency in seconds",
+            ),
+            &["endpoint", "method", "status"],
+        )
+        .expect("valid metric definition");
+        // the route is only known once the request has been routed, so in-flight
+        // requests are counted per method alone
+        let in_flight = IntGaugeVec::new(
+            Opts::new(
+                "http_requests_in_flight",
+                "HTTP requests currently being served",
+            ),
+            &["method"],
+        )
+        .expect("valid metric definition");
+
+        let registry = Registry::new();
+        registry
+            .register(Box::new(requests_total.clone()))
+            .expect("metric registered once");
+        registry
+            .register(Box::new(request_duration.clone()))
+            .expect("metric registered once");
+        registry
+            .register(Box::new(in_flight.clone()))
+            .expect("metric registered once");
+
+        Metrics {
+            registry,
+            requests_total,
+            request_duration,
+            in_flight,
+        }
+    }
+
+    /// Render every collector in the Prometheus text exposition format.
+    pub fn render(&self) -> Result<String, prometheus::Error> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8_lossy(&buffer).into_owned())
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics::new()
+    }
+}
+
+/// `GET /metrics`
+pub async fn metrics(metrics: web::Data<Metrics>) -> HttpResponse {
+    match metrics.render() {
+        Ok(body) => HttpResponse::Ok()
+            .content_type("text/plain; version=0.0.4")
+            .body(body),
+        Err(e) => {
+            log::error!("failed to encode metrics: {}", e);
+            ApiError::Internal.error_response()
+        }
+    }
+}
+
+/// `endpoint` label of requests that matched no route, or were answered by middleware
+/// (auth, rate limiting, load shedding, timeouts) without a routed request to report.
+const UNMATCHED: &str = "unmatched";
+
+/// The route pattern `req` was served by, such as `/v1/store/{api_id}`.
+///
+/// actix-web 2 does not expose the matched pattern, so it is the one `RouteLabel`
+/// recorded on the way in; requests answered before reaching a resource (unknown paths,
+/// CORS preflights, auth) have none.
+pub fn route_pattern(req: &HttpRequest) -> Option<String> {
+    req.extensions()
+        .get::<MatchedRoute>()
+        .map(|route| route.0.to_string())
+}
+
+/// Pattern of the resource serving a request, kept in the request's extensions.
+struct MatchedRoute(Rc<str>);
+
+/// Resource middleware recording `pattern` on every request the resource serves, for
+/// `route_pattern` to report.
+pub struct RouteLabel {
+    pattern: Rc<str>,
+}
+
+impl RouteLabel {
+    pub fn new(pattern: &str) -> Self {
+        RouteLabel {
+            pattern: pattern.into(),
+        }
+    }
+}
+
+impl<S, B> Transform<S> for RouteLabel
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RouteLabelMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RouteLabelMiddleware {
+            service,
+            pattern: self.pattern.clone(),
+        })
+    }
+}
+
+pub struct RouteLabelMiddleware<S> {
+    service: S,
+    pattern: Rc<str>,
+}
+
+impl<S, B> Service for RouteLabelMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        req.extensions_mut()
+            .insert(MatchedRoute(self.pattern.clone()));
+        self.service.call(req)
+    }
+}
+
+/// `web::resource(path)` in the scope mounted at `prefix`, labelled with its full pattern.
+pub fn labelled_resource(
+    prefix: &str,
+    path: &str,
+) -> Resource<
+    impl ServiceFactory<
+        Config = (),
+        Request = ServiceRequest,
+        Response = ServiceResponse,
+        Error = Error,
+        InitError = (),
+    >,
+> {
+    web::resource(path).wrap(RouteLabel::new(&format!("{}{}", prefix, path)))
+}
+
+/// Middleware recording request count, latency and in-flight requests.
+///
+/// Requests are labelled by route pattern rather than raw path, so ids in the URL do not
+/// create a time series each.
+pub struct RequestMetrics {
+    metrics: web::Data<Metrics>,
+}
+
+impl RequestMetrics {
+    pub fn new(metrics: web::Data<Metrics>) -> Self {
+        RequestMetrics { metrics }
+    }
+}
+
+impl<S, B> Transform<S> for RequestMetrics
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RequestMetricsMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RequestMetricsMiddleware {
+            service,
+            metrics: self.metrics.clone(),
+        })
+    }
+}
+
+pub struct RequestMetricsMiddleware<S> {
+    service: S,
+    metrics: web::Data<Metrics>,
+}
+
+impl<S, B> Service for RequestMetricsMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let method = req.method().to_string();
+        let metrics = self.metrics.clone();
+        let in_flight = metrics.in_flight.with_label_values(&[&method]);
+        in_flight.inc();
+        let start = Instant::now();
+
+        let fut = self.service.call(req);
+        Box::pin(async move {
+            let res = fut.await;
+            in_flight.dec();
+            let (endpoint, status) = match &res {
+                Ok(res) => (
+                    route_pattern(res.request()),
+                    res.status().as_u16().to_string(),
+                ),
+                Err(e) => (
+                    None,
+                    e.as_response_error().status_code().as_u16().to_string(),
+                ),
+            };
+            let endpoint = endpoint.unwrap_or_else(|| UNMATCHED.to_string());
+            let labels = [endpoint.as_str(), method.as_str(), status.as_str()];
+            metrics.requests_total.with_label_values(&labels).inc();
+            metrics
+                .request_duration
+                .with_label_values(&labels)
+                .observe(start.elapsed().as_secs_f64());
+            res
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{labelled_resource, Metrics, RequestMetrics};
+    use actix_web::{test, web, App, HttpResponse};
+
+    async fn ok() -> HttpResponse {
+        HttpResponse::Ok().finish()
+    }
+
+    #[actix_rt::test]
+    async fn requests_are_labelled_by_route_pattern() {
+        let metrics = web::Data::new(Metrics::new());
+        let mut app = test::init_service(
+            App::new()
+                .wrap(RequestMetrics::new(metrics.clone()))
+                .service(
+                    web::scope("/v1")
+                        .service(labelled_resource("/v1", "/store/count").route(web::get().to(ok)))
+                        .service(
+                            labelled_resource("/v1", "/store/{api_id}/upsert")
+                                .route(web::get().to(ok)),
+                        )
+                        .service(
+                            labelled_resource("/v1", "/store/{api_id}").route(web::get().to(ok)),
+                        )
+                        .service(
+                            labelled_resource("/v1", "/pair/{left}/{right}")
+                                .route(web::get().to(ok)),
+                        ),
+                ),
+        )
+        .await;
+        for uri in &[
+            "/v1/store/count",
+            "/v1/store/a",
+            "/v1/store/b",
+            // captured values equal to a fixed segment, or to each other
+            "/v1/store/upsert/upsert",
+            "/v1/pair/x/x",
+            "/nowhere/1",
+        ] {
+            let req = test::TestRequest::get().uri(uri).to_request();
+            test::call_service(&mut app, req).await;
+        }
+
+        let rendered = metrics.render().expect("metrics render");
+        let count = |endpoint: &str| {
+            rendered
+                .lines()
+                .find(|line| {
+                    line.starts_with("http_requests_total{")
+                        && line.contains(&format!("endpoint=\"{}\"", endpoint))
+                })
+                .and_then(|line| line.rsplit(' ').next())
+                .map(str::to_string)
+        };
+        assert_eq!(count("/v1/store/{api_id}").as_deref(), Some("2"));
+        assert_eq!(count("/v1/store/count").as_deref(), Some("1"));
+        assert_eq!(count("/v1/store/{api_id}/upsert").as_deref(), Some("1"));
+        assert_eq!(count("/v1/pair/{left}/{right}").as_deref(), Some("1"));
+        assert_eq!(count("unmatched").as_deref(), Some("1"));
+        assert!(!rendered.contains("/v1/store/a"));
+        assert!(!rendered.contains("/v1/pair/x"));
+    }
+}
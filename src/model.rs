@@ -1,22 +1,65 @@
-use super::schema::stores;
+use super::schema::{audit_log, store_revisions, stores};
+use chrono::{DateTime, NaiveDateTime};
 use serde_derive::{Deserialize, Serialize};
 use serde_json::Value;
+use sha2::{Digest, Sha256};
+use utoipa::{IntoParams, ToSchema};
 
-#[derive(Serialize, Deserialize, Queryable)]
+#[derive(Clone, Serialize, Deserialize, Queryable)]
 pub struct Store {
   pub id: i32,
   pub data: String,
   pub api_id: String,
+  pub created_at: NaiveDateTime,
+  pub updated_at: NaiveDateTime,
+  pub version: i32,
+  pub idempotency_key: Option<String>,
+  pub content_hash: Option<String>,
+  /// set instead of removing the row when soft delete is on
+  pub deleted_at: Option<NaiveDateTime>,
 }
 
-impl Into<serde_json::Value> for Store {
-  fn into(self) -> Value {
-    let entry_id = self.api_id;
-    let entry_data = serde_json::from_str::<Value>(&self.data).unwrap();
-    json!({
-        "api_id": entry_id,
-        "data": entry_data
-    })
+impl Store {
+  /// Strong entity tag derived from the stored data and its last update time,
+  /// so the same content always yields the same tag across restarts.
+  pub fn etag(&self) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(self.data.as_bytes());
+    hasher.update(self.updated_at.to_string().as_bytes());
+    let digest = hasher.finalize();
+    let hex: String = digest[..16].iter().map(|b| format!("{:02x}", b)).collect();
+    format!("\"{}\"", hex)
+  }
+}
+
+/// Public shape of a store returned by the API.
+#[derive(Serialize, ToSchema)]
+pub struct StoreResponse {
+  pub id: i32,
+  pub api_id: String,
+  #[schema(value_type = Object)]
+  pub data: Value,
+  pub created_at: NaiveDateTime,
+  pub updated_at: NaiveDateTime,
+  pub version: i32,
+  /// only present on soft-deleted stores listed with `?include_deleted=true`
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub deleted_at: Option<NaiveDateTime>,
+}
+
+impl From<Store> for StoreResponse {
+  fn from(store: Store) -> Self {
+    // data is always written as serialized JSON, fall back to the raw text otherwise
+    let data = serde_json::from_str::<Value>(&store.data).unwrap_or(Value::String(store.data));
+    StoreResponse {
+      id: store.id,
+      api_id: store.api_id,
+      data,
+      created_at: store.created_at,
+      updated_at: store.updated_at,
+      version: store.version,
+      deleted_at: store.deleted_at,
+    }
   }
 }
 
@@ -25,4 +68,263 @@ impl Into<serde_json::Value> for Store {
 pub struct NewStore<'a> {
   pub data: &'a str,
   pub api_id: &'a str,
+  pub created_at: NaiveDateTime,
+  pub updated_at: NaiveDateTime,
+  pub idempotency_key: Option<&'a str>,
+  pub content_hash: Option<&'a str>,
+}
+
+/// Query parameters for paging through `GET /store`.
+///
+/// `limit` defaults to 50 and is capped at 200, `offset` defaults to 0.
+/// Passing `after=<id>` switches to keyset pagination over the numeric id.
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct Pagination {
+  pub limit: Option<i64>,
+  pub offset: Option<i64>,
+  pub after: Option<i32>,
+}
+
+impl Pagination {
+  pub const DEFAULT_LIMIT: i64 = 50;
+  pub const MAX_LIMIT: i64 = 200;
+
+  /// Resolve the effective `(limit, offset)`, rejecting negative values.
+  pub fn resolve(&self) -> Result<(i64, i64), &'static str> {
+    let limit = self.limit.unwrap_or(Self::DEFAULT_LIMIT);
+    let offset = self.offset.unwrap_or(0);
+    if limit < 0 {
+      return Err("limit must not be negative");
+    }
+    if offset < 0 {
+      return Err("offset must not be negative");
+    }
+    Ok((limit.min(Self::MAX_LIMIT), offset))
+  }
+}
+
+/// `?key=&value=` filter on the list endpoint, matching stores whose top-level
+/// `data[key]` equals `value` as text.
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DataFilter {
+  pub key: Option<String>,
+  pub value: Option<String>,
+}
+
+impl DataFilter {
+  const MAX_KEY_LEN: usize = 64;
+
+  /// Resolve to a `(key, value)` pair; keys are restricted to `[A-Za-z0-9_.-]`.
+  pub fn resolve(&self) -> Result<Option<(String, String)>, &'static str> {
+    match (&self.key, &self.value) {
+      (None, None) => Ok(None),
+      (Some(key), Some(value)) => {
+        let valid = !key.is_empty()
+          && key.len() <= Self::MAX_KEY_LEN
+          && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.');
+        if !valid {
+          return Err("key may only contain letters, digits, '_', '-' and '.'");
+        }
+        Ok(Some((key.clone(), value.clone())))
+      }
+      _ => Err("key and value must be given together"),
+    }
+  }
+}
+
+/// `?include_deleted=true` on the list endpoint, revealing soft-deleted stores.
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DeletedFilter {
+  pub include_deleted: Option<bool>,
+}
+
+/// `?created_after=&created_before=` bounds (inclusive, RFC 3339) on the list endpoint.
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DateRange {
+  pub created_after: Option<String>,
+  pub created_before: Option<String>,
+}
+
+/// Resolved creation-time window, in UTC like the stored timestamps.
+#[derive(Clone, Copy)]
+pub struct CreatedWindow {
+  pub after: Option<NaiveDateTime>,
+  pub before: Option<NaiveDateTime>,
+}
+
+impl DateRange {
+  fn parse(raw: &Option<String>, error: &'static str) -> Result<Option<NaiveDateTime>, &'static str> {
+    raw
+      .as_deref()
+      .map(|raw| {
+        DateTime::parse_from_rfc3339(raw)
+          .map(|ts| ts.naive_utc())
+          .map_err(|_| error)
+      })
+      .transpose()
+  }
+
+  pub fn resolve(&self) -> Result<CreatedWindow, &'static str> {
+    let after = Self::parse(
+      &self.created_after,
+      "created_after must be an RFC 3339 timestamp such as 2026-01-31T00:00:00Z",
+    )?;
+    let before = Self::parse(
+      &self.created_before,
+      "created_before must be an RFC 3339 timestamp such as 2026-01-31T23:59:59Z",
+    )?;
+    if let (Some(after), Some(before)) = (after, before) {
+      if after > before {
+        return Err("created_after must not be later than created_before");
+      }
+    }
+    Ok(CreatedWindow { after, before })
+  }
+}
+
+/// `?q=` terms for full-text search, in `websearch_to_tsquery` syntax
+/// (`"exact phrase"`, `or`, `-excluded`).
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SearchQuery {
+  pub q: Option<String>,
+}
+
+impl SearchQuery {
+  /// The trimmed terms, rejecting a missing or blank `q`.
+  pub fn resolve(&self) -> Result<String, &'static str> {
+    match self.q.as_deref().map(str::trim) {
+      Some(terms) if !terms.is_empty() => Ok(terms.to_string()),
+      _ => Err("q must contain at least one search term"),
+    }
+  }
+}
+
+/// Column a list can be sorted by with `?sort=`.
+#[derive(Clone, Copy)]
+pub enum SortField {
+  CreatedAt,
+  UpdatedAt,
+  ApiId,
+}
+
+/// `?sort=&order=` on the list endpoint; without `sort` results are ordered by id.
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct Sorting {
+  pub sort: Option<String>,
+  pub order: Option<String>,
+}
+
+impl Sorting {
+  /// Resolve to the sort column (`None` meaning id) and whether it is descending.
+  pub fn resolve(&self) -> Result<(Option<SortField>, bool), &'static str> {
+    let field = match self.sort.as_deref() {
+      None => None,
+      Some("created_at") => Some(SortField::CreatedAt),
+      Some("updated_at") => Some(SortField::UpdatedAt),
+      Some("api_id") => Some(SortField::ApiId),
+      Some(_) => return Err("sort must be one of created_at, updated_at, api_id"),
+    };
+    let descending = match self.order.as_deref() {
+      None | Some("asc") => false,
+      Some("desc") => true,
+      Some(_) => return Err("order must be asc or desc"),
+    };
+    Ok((field, descending))
+  }
+
+  /// Whether anything other than the default `id asc` was requested.
+  pub fn is_default(&self) -> bool {
+    self.sort.is_none() && self.order.is_none()
+  }
+}
+
+/// Page returned by offset pagination, with the paging context it was cut with.
+#[derive(Serialize, ToSchema)]
+pub struct Paginated<T> {
+  pub data: Vec<T>,
+  /// rows matching the filters across all pages
+  pub total: i64,
+  pub limit: i64,
+  pub offset: i64,
+}
+
+/// Page returned by keyset pagination; `next_cursor` is `null` on the last page.
+#[derive(Serialize, ToSchema)]
+pub struct KeysetPage<T> {
+  pub data: Vec<T>,
+  pub next_cursor: Option<i32>,
+}
+
+/// One recorded mutation of a store.
+#[derive(Serialize, Queryable, ToSchema)]
+pub struct AuditEntry {
+  pub id: i32,
+  /// `create`, `update` or `delete`
+  pub action: String,
+  pub api_id: String,
+  /// who made the change; `null` when authentication is disabled
+  pub principal: Option<String>,
+  pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "audit_log"]
+pub struct NewAuditEntry<'a> {
+  pub action: &'a str,
+  pub api_id: &'a str,
+  pub principal: Option<&'a str>,
+  pub created_at: NaiveDateTime,
+}
+
+/// Value a store held before one of its updates.
+#[derive(Queryable)]
+pub struct Revision {
+  pub id: i32,
+  pub api_id: String,
+  pub data: String,
+  pub version: i32,
+  pub changed_at: NaiveDateTime,
+}
+
+/// Public shape of a revision returned by `GET /store/{api_id}/history`.
+#[derive(Serialize, ToSchema)]
+pub struct RevisionResponse {
+  pub id: i32,
+  pub api_id: String,
+  #[schema(value_type = Object)]
+  pub data: Value,
+  /// version the store had while it held `data`
+  pub version: i32,
+  /// when `data` was replaced
+  pub changed_at: NaiveDateTime,
+}
+
+impl From<Revision> for RevisionResponse {
+  fn from(revision: Revision) -> Self {
+    let data = serde_json::from_str::<Value>(&revision.data).unwrap_or(Value::String(revision.data));
+    RevisionResponse {
+      id: revision.id,
+      api_id: revision.api_id,
+      data,
+      version: revision.version,
+      changed_at: revision.changed_at,
+    }
+  }
+}
+
+#[derive(Insertable)]
+#[table_name = "store_revisions"]
+pub struct NewRevision<'a> {
+  pub api_id: &'a str,
+  pub data: &'a str,
+  pub version: i32,
+  pub changed_at: NaiveDateTime,
 }
@@ -0,0 +1,75 @@
+use crate::error::{ErrorBody, ProblemDetails};
+use crate::model::{AuditEntry, KeysetPage, Paginated, RevisionResponse, StoreResponse};
+use actix_web::HttpResponse;
+use utoipa::OpenApi;
+
+/// OpenAPI description of the store API, generated from the handler annotations.
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "state", description = "JSON document store"),
+    paths(
+        crate::live,
+        crate::ready,
+        crate::build_info,
+        crate::create_store,
+        crate::create_stores_batch,
+        crate::list_stores,
+        crate::count_stores,
+        crate::search_stores,
+        crate::get_store,
+        crate::get_store_by_id,
+        crate::update_store,
+        crate::upsert_store,
+        crate::patch_store,
+        crate::delete_store,
+        crate::delete_stores_batch,
+        crate::revisions::list_history,
+        crate::audit::list_audit,
+    ),
+    components(schemas(
+        StoreResponse,
+        Paginated<StoreResponse>,
+        KeysetPage<StoreResponse>,
+        RevisionResponse,
+        Paginated<RevisionResponse>,
+        AuditEntry,
+        Paginated<AuditEntry>,
+        ErrorBody,
+        ProblemDetails
+    )),
+    tags(
+        (name = "stores", description = "Create, read, update and delete stores"),
+        (name = "audit", description = "Trail of store mutations")
+    )
+)]
+pub struct ApiDoc;
+
+/// Served at `/swagger-ui`; the UI assets are loaded from a CDN.
+const SWAGGER_UI_HTML: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <title>state API</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+  <script>
+    window.ui = SwaggerUIBundle({ url: "/api-docs/openapi.json", dom_id: "#swagger-ui" });
+  </script>
+</body>
+</html>
+"##;
+
+/// `GET /api-docs/openapi.json`
+pub async fn openapi_json() -> HttpResponse {
+    HttpResponse::Ok().json(ApiDoc::openapi())
+}
+
+/// `GET /swagger-ui`
+pub async fn swagger_ui() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(SWAGGER_UI_HTML)
+}
@@ -0,0 +1,152 @@
+use crate::error::ApiError;
+use json_patch::{Patch, PatchErrorKind};
+use serde_json::{Map, Value};
+
+/// `Content-Type` of an RFC 7386 JSON Merge Patch body.
+pub const MERGE_PATCH_CONTENT_TYPE: &str = "application/merge-patch+json";
+
+/// `Content-Type` of an RFC 6902 JSON Patch body.
+pub const JSON_PATCH_CONTENT_TYPE: &str = "application/json-patch+json";
+
+/// A `PATCH /v1/store/{api_id}` body, in the format named by its `Content-Type`.
+pub enum StorePatch {
+    Merge(Value),
+    Json(Patch),
+}
+
+impl StorePatch {
+    pub fn parse(content_type: Option<&str>, body: &[u8]) -> Result<Self, ApiError> {
+        let invalid = |e: serde_json::Error| ApiError::BadRequest(format!("invalid patch: {}", e));
+        match content_type {
+            Some(MERGE_PATCH_CONTENT_TYPE) => serde_json::from_slice(body)
+                .map(StorePatch::Merge)
+                .map_err(invalid),
+            Some(JSON_PATCH_CONTENT_TYPE) => serde_json::from_slice(body)
+                .map(StorePatch::Json)
+                .map_err(invalid),
+            _ => Err(ApiError::UnsupportedMediaType),
+        }
+    }
+
+    /// Apply the patch to `document`, leaving it untouched when a JSON Patch operation fails.
+    ///
+    /// A failed `test` operation is a `409`, any other failed operation a `422`.
+    pub fn apply(&self, document: &mut Value) -> Result<(), ApiError> {
+        match self {
+            StorePatch::Merge(patch) => {
+                merge(document, patch);
+                Ok(())
+            }
+            StorePatch::Json(patch) => {
+                json_patch::patch(document, patch).map_err(|e| match e.kind {
+                    PatchErrorKind::TestFailed => ApiError::Conflict(e.to_string()),
+                    _ => ApiError::Validation(vec![e.to_string()]),
+                })
+            }
+        }
+    }
+}
+
+/// Apply an RFC 7386 JSON Merge Patch to `target` in place.
+///
+/// Objects are merged recursively and members set to `null` are removed; any other
+/// patch value replaces the target outright.
+pub fn merge(target: &mut Value, patch: &Value) {
+    let patch = match patch {
+        Value::Object(patch) => patch,
+        other => {
+            *target = other.clone();
+            return;
+        }
+    };
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    let map = target
+        .as_object_mut()
+        .expect("target was just made an object");
+    for (key, value) in patch {
+        if value.is_null() {
+            map.remove(key);
+        } else {
+            merge(map.entry(key.as_str()).or_insert(Value::Null), value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{merge, StorePatch, JSON_PATCH_CONTENT_TYPE};
+    use crate::error::ApiError;
+
+    fn merged(target: serde_json::Value, patch: serde_json::Value) -> serde_json::Value {
+        let mut target = target;
+        merge(&mut target, &patch);
+        target
+    }
+
+    #[test]
+    fn nested_objects_are_merged() {
+        assert_eq!(
+            merged(
+                json!({ "a": "b", "c": { "d": "e", "f": "g" } }),
+                json!({ "a": "z", "c": { "f": null } })
+            ),
+            json!({ "a": "z", "c": { "d": "e" } })
+        );
+    }
+
+    #[test]
+    fn arrays_and_scalars_are_replaced() {
+        assert_eq!(
+            merged(json!({ "a": [1, 2] }), json!({ "a": [3] })),
+            json!({ "a": [3] })
+        );
+        assert_eq!(merged(json!({ "a": "b" }), json!(["c"])), json!(["c"]));
+    }
+
+    #[test]
+    fn object_patch_replaces_a_scalar_member() {
+        assert_eq!(
+            merged(json!({ "a": "b" }), json!({ "a": { "bb": null, "c": 1 } })),
+            json!({ "a": { "c": 1 } })
+        );
+    }
+
+    #[test]
+    fn failed_json_patch_test_is_a_conflict_and_leaves_the_document() {
+        let patch = StorePatch::parse(
+            Some(JSON_PATCH_CONTENT_TYPE),
+            br#"[{"op":"replace","path":"/a","value":2},{"op":"test","path":"/b","value":1}]"#,
+        )
+        .expect("valid patch");
+        let mut document = json!({ "a": 1, "b": 0 });
+        assert!(matches!(
+            patch.apply(&mut document),
+            Err(ApiError::Conflict(_))
+        ));
+        assert_eq!(document, json!({ "a": 1, "b": 0 }));
+    }
+
+    #[test]
+    fn json_patch_on_a_missing_path_is_a_validation_error() {
+        let patch = StorePatch::parse(
+            Some(JSON_PATCH_CONTENT_TYPE),
+            br#"[{"op":"remove","path":"/missing"}]"#,
+        )
+        .expect("valid patch");
+        let mut document = json!({ "a": 1 });
+        assert!(matches!(
+            patch.apply(&mut document),
+            Err(ApiError::Validation(_))
+        ));
+    }
+
+    #[test]
+    fn unknown_content_type_is_unsupported() {
+        assert!(matches!(
+            StorePatch::parse(Some("application/json"), b"{}"),
+            Err(ApiError::UnsupportedMediaType)
+        ));
+    }
+}
@@ -0,0 +1,258 @@
+use crate::config::Settings;
+use crate::error::ApiError;
+use actix_service::{Service, Transform};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::{web, Error};
+use futures::future::{ok, Ready};
+use std::collections::HashMap;
+use std::future::Future;
+use std::io;
+use std::net::IpAddr;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+/// How often idle buckets are swept out of the map.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+struct Buckets {
+    by_ip: HashMap<IpAddr, Bucket>,
+    last_prune: Instant,
+}
+
+/// Per-IP token buckets shared by every worker.
+pub struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    trusted_proxies: Vec<IpAddr>,
+    buckets: Mutex<Buckets>,
+}
+
+/// `TRUSTED_PROXIES` as addresses, failing on the first entry that is not an IP.
+pub fn trusted_proxies_from_settings(settings: &Settings) -> io::Result<Vec<IpAddr>> {
+    settings
+        .trusted_proxies
+        .iter()
+        .map(|proxy| {
+            proxy.parse::<IpAddr>().map_err(|e| {
+                io::Error::other(format!("invalid TRUSTED_PROXIES entry {:?}: {}", proxy, e))
+            })
+        })
+        .collect()
+}
+
+impl RateLimiter {
+    /// `rate` tokens are added per second, up to `burst` tokens per client.
+    pub fn new(rate: f64, burst: f64) -> Self {
+        RateLimiter {
+            rate,
+            burst,
+            trusted_proxies: Vec::new(),
+            buckets: Mutex::new(Buckets {
+                by_ip: HashMap::new(),
+                last_prune: Instant::now(),
+            }),
+        }
+    }
+
+    /// Charge requests arriving from `proxies` to the address they forwarded for.
+    pub fn with_trusted_proxies(mut self, proxies: Vec<IpAddr>) -> Self {
+        self.trusted_proxies = proxies;
+        self
+    }
+
+    /// The address a request is charged to: its peer, or, when the peer is a trusted
+    /// proxy, the last `X-Forwarded-For` entry that is not itself a trusted proxy.
+    ///
+    /// Anything a client can write itself is never believed, so a made-up forwarding
+    /// header cannot buy a fresh bucket.
+    fn client_ip(&self, req: &ServiceRequest) -> Option<IpAddr> {
+        let peer = req.peer_addr()?.ip();
+        if !self.trusted_proxies.contains(&peer) {
+            return Some(peer);
+        }
+        let forwarded = req
+            .headers()
+            .get_all("x-forwarded-for")
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .collect::<Vec<_>>();
+        for hop in forwarded.into_iter().rev() {
+            match hop.parse::<IpAddr>() {
+                Ok(ip) if self.trusted_proxies.contains(&ip) => continue,
+                Ok(ip) => return Some(ip),
+                Err(_) => break,
+            }
+        }
+        Some(peer)
+    }
+
+    /// Take a token for `ip`, or return how long until one is available.
+    pub fn check(&self, ip: IpAddr) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        if now.duration_since(buckets.last_prune) >= PRUNE_INTERVAL {
+            self.prune(&mut buckets.by_ip, now);
+            buckets.last_prune = now;
+        }
+
+        let burst = self.burst;
+        let bucket = buckets.by_ip.entry(ip).or_insert(Bucket {
+            tokens: burst,
+            last_refill: now,
+        });
+        let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.burst);
+        bucket.last_refill = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / self.rate))
+        }
+    }
+
+    /// Drop buckets that have refilled completely; they are equivalent to a fresh one.
+    fn prune(&self, by_ip: &mut HashMap<IpAddr, Bucket>, now: Instant) {
+        let (rate, burst) = (self.rate, self.burst);
+        by_ip.retain(|_, bucket| {
+            let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+            bucket.tokens + elapsed * rate < burst
+        });
+    }
+}
+
+/// Middleware answering `429 Too Many Requests` once a client's bucket is empty.
+pub struct RateLimit {
+    limiter: web::Data<RateLimiter>,
+}
+
+impl RateLimit {
+    pub fn new(limiter: web::Data<RateLimiter>) -> Self {
+        RateLimit { limiter }
+    }
+}
+
+impl<S, B> Transform<S> for RateLimit
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RateLimitMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RateLimitMiddleware {
+            service,
+            limiter: self.limiter.clone(),
+        })
+    }
+}
+
+pub struct RateLimitMiddleware<S> {
+    service: S,
+    limiter: web::Data<RateLimiter>,
+}
+
+impl<S, B> Service for RateLimitMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        if let Some(ip) = self.limiter.client_ip(&req) {
+            if let Err(wait) = self.limiter.check(ip) {
+                let retry_after = wait.as_secs_f64().ceil() as u64;
+                return Box::pin(ok(req.error_response(ApiError::RateLimited(retry_after))));
+            }
+        }
+        Box::pin(self.service.call(req))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RateLimit, RateLimiter};
+    use actix_web::http::StatusCode;
+    use actix_web::{test, web, App, HttpResponse};
+
+    async fn ok() -> HttpResponse {
+        HttpResponse::Ok().finish()
+    }
+
+    async fn status_for(limiter: &web::Data<RateLimiter>, forwarded_for: &[&str]) -> Vec<u16> {
+        let mut app = test::init_service(
+            App::new()
+                .wrap(RateLimit::new(limiter.clone()))
+                .route("/", web::get().to(ok)),
+        )
+        .await;
+        let mut statuses = Vec::new();
+        for client in forwarded_for {
+            let req = test::TestRequest::get()
+                .uri("/")
+                .peer_addr("10.0.0.1:4000".parse().expect("socket address"))
+                .header("X-Forwarded-For", *client)
+                .to_request();
+            statuses.push(test::call_service(&mut app, req).await.status().as_u16());
+        }
+        statuses
+    }
+
+    #[actix_rt::test]
+    async fn spoofed_forwarded_for_shares_the_peers_bucket() {
+        let limiter = web::Data::new(RateLimiter::new(0.001, 1.0));
+        let statuses = status_for(&limiter, &["203.0.113.1", "203.0.113.2"]).await;
+        assert_eq!(
+            statuses,
+            [
+                StatusCode::OK.as_u16(),
+                StatusCode::TOO_MANY_REQUESTS.as_u16()
+            ]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn trusted_proxies_charge_the_forwarded_client() {
+        let limiter = web::Data::new(
+            RateLimiter::new(0.001, 1.0)
+                .with_trusted_proxies(vec!["10.0.0.1".parse().expect("IP address")]),
+        );
+        let statuses = status_for(
+            &limiter,
+            &["203.0.113.1", "203.0.113.2", "198.51.100.7, 203.0.113.1"],
+        )
+        .await;
+        assert_eq!(
+            statuses,
+            [
+                StatusCode::OK.as_u16(),
+                StatusCode::OK.as_u16(),
+                StatusCode::TOO_MANY_REQUESTS.as_u16()
+            ]
+        );
+    }
+}
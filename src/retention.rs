@@ -0,0 +1,67 @@
+use crate::cache::StoreCache;
+use crate::db;
+use crate::error::ApiError;
+use crate::schema::stores::dsl::*;
+use crate::Pool;
+use actix_web::web;
+use diesel::prelude::*;
+use std::time::Duration;
+
+/// Delete every store created more than `retention` ago, returning how many went.
+pub async fn prune_once(pool: Pool, retention: chrono::Duration) -> Result<usize, ApiError> {
+    web::block(move || {
+        let cutoff = chrono::Utc::now().naive_utc() - retention;
+        db::with_connection(&pool, |conn| {
+            Ok(diesel::delete(stores.filter(created_at.lt(cutoff))).execute(conn)?)
+        })
+    })
+    .await
+    .map_err(ApiError::from)
+}
+
+/// Hard-delete stores soft-deleted more than `grace` ago, returning how many went.
+pub async fn purge_once(pool: Pool, grace: chrono::Duration) -> Result<usize, ApiError> {
+    web::block(move || {
+        let cutoff = chrono::Utc::now().naive_utc() - grace;
+        db::with_connection(&pool, |conn| {
+            Ok(diesel::delete(stores.filter(deleted_at.lt(cutoff))).execute(conn)?)
+        })
+    })
+    .await
+    .map_err(ApiError::from)
+}
+
+/// Prune expired stores, and purge soft-deleted ones past `grace`, every `interval`,
+/// starting immediately.
+///
+/// A failed run is logged and retried on the next tick rather than ending the task.
+pub async fn prune_periodically(
+    pool: Pool,
+    cache: web::Data<StoreCache>,
+    retention: Option<chrono::Duration>,
+    grace: Option<chrono::Duration>,
+    interval: Duration,
+) {
+    let mut ticker = actix_rt::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        if let Some(retention) = retention {
+            match prune_once(pool.clone(), retention).await {
+                Ok(deleted) => {
+                    log::info!("retention pruned {} store(s)", deleted);
+                    if deleted > 0 {
+                        cache.invalidate_all();
+                    }
+                }
+                Err(e) => log::error!("retention run failed: {:?}", e),
+            }
+        }
+        if let Some(grace) = grace {
+            // soft-deleted stores are never cached, so the cache needs no invalidation
+            match purge_once(pool.clone(), grace).await {
+                Ok(purged) => log::info!("purged {} soft-deleted store(s)", purged),
+                Err(e) => log::error!("soft delete purge failed: {:?}", e),
+            }
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use crate::db;
+use crate::error::{ApiError, ErrorBody};
+use crate::model::{NewRevision, Paginated, Pagination, Revision, RevisionResponse, Store};
+use crate::schema;
+use crate::schema::store_revisions::dsl::*;
+use crate::store_id::StoreId;
+use crate::ReadPool;
+use actix_web::{web, HttpResponse};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use serde_derive::Deserialize;
+use utoipa::IntoParams;
+
+/// Keep `previous`, the store as it was before an update, in its history.
+///
+/// Call it inside the update's transaction, after locking the row, so the history and
+/// the current value can never disagree.
+pub fn record(conn: &PgConnection, previous: &Store) -> QueryResult<()> {
+    diesel::insert_into(store_revisions)
+        .values(&NewRevision {
+            api_id: &previous.api_id,
+            data: &previous.data,
+            version: previous.version,
+            changed_at: chrono::Utc::now().naive_utc(),
+        })
+        .execute(conn)
+        .map(|_| ())
+}
+
+/// Query parameters of `GET /v1/store/{api_id}/history`; `limit` and `offset` behave as
+/// on the store list.
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct HistoryQuery {
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/store/{api_id}/history",
+    tag = "stores",
+    params(("api_id" = String, Path, description = "Store id"), HistoryQuery),
+    responses(
+        (status = 200, description = "Earlier values of the store, newest first",
+            body = Paginated<RevisionResponse>),
+        (status = 400, description = "Malformed api_id or query parameters", body = ErrorBody),
+        (status = 404, description = "No such store", body = ErrorBody),
+    )
+)]
+/// page through the values a store held before each of its updates, newest first
+pub async fn list_history(
+    path: StoreId,
+    query: web::Query<HistoryQuery>,
+    pool: web::Data<ReadPool>,
+) -> Result<HttpResponse, ApiError> {
+    let pagination = Pagination {
+        limit: query.limit,
+        offset: query.offset,
+        after: None,
+    };
+    let (limit, offset) = pagination
+        .resolve()
+        .map_err(|msg| ApiError::BadRequest(msg.to_string()))?;
+    let target = path.into_inner();
+    let (revisions, total) = db::block(move || {
+        db::with_connection(&pool, |conn| {
+            let live = schema::stores::table
+                .filter(schema::stores::api_id.eq(&target))
+                .filter(schema::stores::deleted_at.is_null());
+            if !diesel::select(diesel::dsl::exists(live)).get_result::<bool>(conn)? {
+                return Err(ApiError::NotFound);
+            }
+            let total = store_revisions
+                .filter(api_id.eq(&target))
+                .count()
+                .get_result::<i64>(conn)?;
+            let revisions = store_revisions
+                .filter(api_id.eq(&target))
+                .order(id.desc())
+                .limit(limit)
+                .offset(offset)
+                .load::<Revision>(conn)?;
+            Ok((revisions, total))
+        })
+    })
+    .await?;
+    Ok(HttpResponse::Ok().json(Paginated {
+        data: revisions
+            .into_iter()
+            .map(RevisionResponse::from)
+            .collect::<Vec<_>>(),
+        total,
+        limit,
+        offset,
+    }))
+}
@@ -3,5 +3,31 @@ table! {
         id -> Int4,
         data -> Text,
         api_id -> Text,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        version -> Int4,
+        idempotency_key -> Nullable<Text>,
+        content_hash -> Nullable<Text>,
+        deleted_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    audit_log (id) {
+        id -> Int4,
+        action -> Text,
+        api_id -> Text,
+        principal -> Nullable<Text>,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    store_revisions (id) {
+        id -> Int4,
+        api_id -> Text,
+        data -> Text,
+        version -> Int4,
+        changed_at -> Timestamp,
     }
 }
@@ -0,0 +1,24 @@
+use crate::config::Settings;
+
+/// Whether deletes only stamp `deleted_at`, and how long such stores are kept.
+///
+/// Off unless `SOFT_DELETE` is set; reads hide soft-deleted stores either way.
+#[derive(Clone, Copy)]
+pub struct SoftDelete {
+    pub enabled: bool,
+    /// how long a soft-deleted store can be recovered before the purge removes it
+    pub grace: chrono::Duration,
+}
+
+impl SoftDelete {
+    pub fn new(enabled: bool, grace: chrono::Duration) -> Self {
+        SoftDelete { enabled, grace }
+    }
+
+    pub fn from_settings(settings: &Settings) -> Self {
+        SoftDelete::new(
+            settings.soft_delete,
+            chrono::Duration::days(settings.soft_delete_grace_days.into()),
+        )
+    }
+}
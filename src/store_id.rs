@@ -0,0 +1,69 @@
+use crate::config::Settings;
+use crate::error::ApiError;
+use actix_web::dev::Payload;
+use actix_web::{FromRequest, HttpRequest};
+use futures::future::{ready, Ready};
+use std::io;
+use uuid::Uuid;
+
+/// UUID version assigned to new stores, from `STORE_ID_VERSION`.
+///
+/// v7 ids embed their creation time, so they sort roughly in insert order.
+#[derive(Clone, Copy, PartialEq)]
+pub enum IdVersion {
+    V4,
+    V7,
+}
+
+impl IdVersion {
+    pub fn from_settings(settings: &Settings) -> io::Result<Self> {
+        match settings.store_id_version.as_deref() {
+            None | Some("4") | Some("v4") => Ok(IdVersion::V4),
+            Some("7") | Some("v7") => Ok(IdVersion::V7),
+            Some(other) => Err(io::Error::other(format!(
+                "invalid STORE_ID_VERSION={:?}: expected 4 or 7",
+                other
+            ))),
+        }
+    }
+
+    /// A fresh api_id in the lowercase hyphenated form.
+    pub fn generate(self) -> String {
+        match self {
+            IdVersion::V4 => Uuid::new_v4().to_string(),
+            IdVersion::V7 => Uuid::now_v7().to_string(),
+        }
+    }
+}
+
+/// `{api_id}` path segment, checked to be a UUID before any database work.
+///
+/// The id is normalised to the lowercase hyphenated form stores are created with.
+pub struct StoreId(String);
+
+impl StoreId {
+    pub fn into_inner(self) -> String {
+        self.0
+    }
+}
+
+/// Normalise a client supplied id, `None` when it is not a UUID.
+pub fn normalize(raw: &str) -> Option<String> {
+    Uuid::parse_str(raw).ok().map(|uuid| uuid.to_string())
+}
+
+impl FromRequest for StoreId {
+    type Config = ();
+    type Error = ApiError;
+    type Future = Ready<Result<Self, ApiError>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let parsed = req
+            .match_info()
+            .get("api_id")
+            .and_then(normalize)
+            .map(StoreId)
+            .ok_or_else(|| ApiError::BadRequest("invalid api_id".to_string()));
+        ready(parsed)
+    }
+}
@@ -0,0 +1,187 @@
+use crate::config::Settings;
+use crate::metrics::route_pattern;
+use crate::request_id::RequestId;
+use actix_service::{Service, Transform};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::HeaderMap;
+use actix_web::{Error, HttpMessage};
+use futures::future::{ok, Ready};
+use opentelemetry::propagation::{Extractor, TextMapPropagator};
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use opentelemetry_sdk::Resource;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tracing::field::Empty;
+use tracing::Instrument;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::layer::SubscriberExt;
+
+/// Export spans over OTLP/HTTP when `OTEL_EXPORTER_OTLP_ENDPOINT` is set.
+///
+/// Spans are batched and sent from a background thread, so exporting never blocks a
+/// worker. The returned provider must be shut down on exit to flush the last batch.
+pub fn init(settings: &Settings) -> io::Result<Option<SdkTracerProvider>> {
+    let endpoint = match &settings.otel_exporter_otlp_endpoint {
+        Some(endpoint) => endpoint,
+        None => return Ok(None),
+    };
+    // like the OTel SDKs, treat the setting as a base URL for every signal
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(format!("{}/v1/traces", endpoint.trim_end_matches('/')))
+        .build()
+        .map_err(|e| io::Error::other(format!("OTEL_EXPORTER_OTLP_ENDPOINT is unusable: {}", e)))?;
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(
+            Resource::builder()
+                .with_service_name(settings.otel_service_name.clone())
+                .build(),
+        )
+        .build();
+    let layer = tracing_opentelemetry::layer().with_tracer(provider.tracer("state"));
+    tracing::subscriber::set_global_default(tracing_subscriber::registry().with(layer))
+        .map_err(|e| io::Error::other(format!("could not install the tracer: {}", e)))?;
+    Ok(Some(provider))
+}
+
+/// Request headers as seen by the W3C trace context propagator.
+struct RequestHeaders<'a>(&'a HeaderMap);
+
+impl Extractor for RequestHeaders<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|name| name.as_str()).collect()
+    }
+}
+
+/// The caller's trace from its `traceparent` header, or an empty context to start a new one.
+fn parent_context(headers: &HeaderMap) -> opentelemetry::Context {
+    TraceContextPropagator::new().extract(&RequestHeaders(headers))
+}
+
+/// Middleware opening a server span per request, continuing the caller's trace.
+///
+/// The span carries the request id, and the route pattern and status once answered;
+/// database work started by the handler through `db::block` nests under it.
+pub struct RequestTracing;
+
+impl<S, B> Transform<S> for RequestTracing
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RequestTracingMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RequestTracingMiddleware { service })
+    }
+}
+
+pub struct RequestTracingMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service for RequestTracingMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let method = req.method().to_string();
+        let request_id = req
+            .extensions()
+            .get::<RequestId>()
+            .map(ToString::to_string)
+            .unwrap_or_default();
+        // renamed to `<method> <route>` once routing has picked a pattern
+        let span = tracing::info_span!(
+            "request",
+            otel.name = %method,
+            otel.kind = "server",
+            otel.status_code = Empty,
+            http.request.method = %method,
+            url.path = %req.path(),
+            http.route = Empty,
+            http.response.status_code = Empty,
+            request_id = %request_id,
+        );
+        span.set_parent(parent_context(req.headers()));
+
+        let fut = span.in_scope(|| self.service.call(req));
+        let recorded = span.clone();
+        Box::pin(
+            async move {
+                let res = fut.await;
+                let status = match &res {
+                    Ok(res) => {
+                        if let Some(route) = route_pattern(res.request()) {
+                            recorded.record("otel.name", format!("{} {}", method, route));
+                            recorded.record("http.route", route.as_str());
+                        }
+                        res.status()
+                    }
+                    Err(e) => e.as_response_error().status_code(),
+                };
+                recorded.record("http.response.status_code", status.as_u16());
+                if status.is_server_error() {
+                    recorded.record("otel.status_code", "ERROR");
+                }
+                res
+            }
+            .instrument(span),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parent_context;
+    use actix_web::test::TestRequest;
+    use opentelemetry::trace::TraceContextExt;
+
+    #[test]
+    fn traceparent_continues_the_callers_trace() {
+        let req = TestRequest::default()
+            .header(
+                "traceparent",
+                "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            )
+            .to_http_request();
+        let cx = parent_context(req.headers());
+        let parent = cx.span().span_context().clone();
+        assert!(parent.is_remote());
+        assert_eq!(
+            parent.trace_id().to_string(),
+            "4bf92f3577b34da6a3ce929d0e0e4736"
+        );
+        assert_eq!(parent.span_id().to_string(), "00f067aa0ba902b7");
+
+        let cx = parent_context(TestRequest::default().to_http_request().headers());
+        assert!(!cx.span().span_context().is_valid());
+    }
+}
@@ -0,0 +1,1016 @@
+//! Database tests run inside `test_transaction` and HTTP tests over a pool whose
+//! connections never commit, so nothing they write persists.
+//!
+//! Both need `TEST_DATABASE_URL` pointing at a disposable Postgres database and are
+//! skipped when it is unset; HTTP tests that never reach the database always run.
+
+use crate::cache::StoreCache;
+use crate::cors::CorsSettings;
+use crate::db;
+use crate::dedupe::Dedupe;
+use crate::error::ApiError;
+use crate::events::StoreEvents;
+use crate::metrics::Metrics;
+use crate::model::{NewStore, Store};
+use crate::rate_limit::RateLimiter;
+use crate::schema::stores::dsl::*;
+use crate::soft_delete::SoftDelete;
+use crate::store_id::IdVersion;
+use crate::validation::StoreSchema;
+use crate::{build_app, AppState, Limits, Pool, ReadPool};
+use actix_service::Service;
+use actix_web::dev::ServiceResponse;
+use actix_web::http::{header, Method, StatusCode};
+use actix_web::{test, web};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::r2d2::{self, ConnectionManager, CustomizeConnection};
+use serde_json::Value;
+use std::sync::atomic::AtomicUsize;
+use std::sync::{Arc, Once};
+use tokio::sync::Semaphore;
+
+static MIGRATE: Once = Once::new();
+
+/// Connect to `TEST_DATABASE_URL` with every migration applied, `None` when unset.
+pub fn establish_test_connection() -> Option<PgConnection> {
+    let url = std::env::var("TEST_DATABASE_URL").ok()?;
+    let conn = PgConnection::establish(&url).expect("could not connect to TEST_DATABASE_URL");
+    MIGRATE.call_once(|| {
+        crate::embedded_migrations::run(&conn).expect("migrations apply to the test database")
+    });
+    Some(conn)
+}
+
+/// Run `body` in a transaction that is always rolled back.
+pub fn with_test_transaction<F>(body: F)
+where
+    F: FnOnce(&PgConnection) -> Result<(), diesel::result::Error>,
+{
+    let conn = match establish_test_connection() {
+        Some(conn) => conn,
+        None => {
+            eprintln!("TEST_DATABASE_URL is not set, skipping");
+            return;
+        }
+    };
+    conn.test_transaction(|| body(&conn));
+}
+
+fn insert(conn: &PgConnection, document: &str) -> Result<Store, diesel::result::Error> {
+    let now = chrono::Utc::now().naive_utc();
+    let uuid = uuid::Uuid::new_v4().to_string();
+    diesel::insert_into(stores)
+        .values(&NewStore {
+            data: document,
+            api_id: &uuid,
+            created_at: now,
+            updated_at: now,
+            idempotency_key: None,
+            content_hash: None,
+        })
+        .get_result(conn)
+}
+
+#[test]
+fn inserted_store_can_be_read_back() {
+    with_test_transaction(|conn| {
+        let created = insert(conn, r#"{"name":"a"}"#)?;
+        let loaded = stores
+            .filter(api_id.eq(&created.api_id))
+            .first::<Store>(conn)?;
+        assert_eq!(loaded.id, created.id);
+        assert_eq!(loaded.data, r#"{"name":"a"}"#);
+        assert_eq!(loaded.version, 1);
+        Ok(())
+    });
+}
+
+#[test]
+fn duplicate_api_id_is_a_unique_violation() {
+    with_test_transaction(|conn| {
+        let created = insert(conn, "{}")?;
+        let now = chrono::Utc::now().naive_utc();
+        let duplicate = diesel::insert_into(stores)
+            .values(&NewStore {
+                data: "{}",
+                api_id: &created.api_id,
+                created_at: now,
+                updated_at: now,
+                idempotency_key: None,
+                content_hash: None,
+            })
+            .execute(conn);
+        assert!(matches!(
+            crate::insert_error(duplicate.unwrap_err()),
+            crate::ApiError::Conflict(_)
+        ));
+        Ok(())
+    });
+}
+
+#[test]
+fn deleted_store_is_gone() {
+    with_test_transaction(|conn| {
+        let created = insert(conn, "{}")?;
+        let deleted = diesel::delete(stores.find(created.id)).execute(conn)?;
+        assert_eq!(deleted, 1);
+        let missing = stores.find(created.id).first::<Store>(conn).optional()?;
+        assert!(missing.is_none());
+        Ok(())
+    });
+}
+
+#[test]
+fn revisions_outlive_their_store() {
+    with_test_transaction(|conn| {
+        let created = insert(conn, r#"{"name":"a"}"#)?;
+        crate::revisions::record(conn, &created)?;
+        diesel::delete(stores.find(created.id)).execute(conn)?;
+        let kept = crate::schema::store_revisions::table
+            .filter(crate::schema::store_revisions::api_id.eq(&created.api_id))
+            .count()
+            .get_result::<i64>(conn)?;
+        assert_eq!(kept, 1);
+        Ok(())
+    });
+}
+
+/// Pool customizer opening a never-committed transaction on every connection.
+#[derive(Debug)]
+struct RollbackOnRelease;
+
+impl CustomizeConnection<PgConnection, r2d2::Error> for RollbackOnRelease {
+    fn on_acquire(&self, conn: &mut PgConnection) -> Result<(), r2d2::Error> {
+        conn.begin_test_transaction()
+            .map_err(r2d2::Error::QueryError)
+    }
+}
+
+/// App state over a single-connection pool whose writes are rolled back, `None` when
+/// `TEST_DATABASE_URL` is unset.
+///
+/// Sharing one connection lets later requests in a test see earlier writes.
+fn test_state() -> Option<AppState> {
+    // applies migrations outside any transaction before the pool opens its own
+    establish_test_connection()?;
+    let url = std::env::var("TEST_DATABASE_URL").ok()?;
+    let pool = r2d2::Pool::builder()
+        .max_size(1)
+        .connection_customizer(Box::new(RollbackOnRelease))
+        .build(ConnectionManager::<PgConnection>::new(url))
+        .expect("could not build the test pool");
+    Some(state_over(pool))
+}
+
+/// App state over a pool that never connects, for tests that stay off the database.
+fn offline_state() -> AppState {
+    state_over(
+        r2d2::Pool::builder()
+            .max_size(1)
+            .min_idle(Some(0))
+            .build_unchecked(ConnectionManager::<PgConnection>::new(
+                "postgres://localhost/unused",
+            )),
+    )
+}
+
+fn state_over(pool: Pool) -> AppState {
+    AppState {
+        read_pool: ReadPool(pool.clone()),
+        pool,
+        schema: web::Data::new(StoreSchema::default()),
+        metrics: web::Data::new(Metrics::new()),
+        limits: web::Data::new(Limits {
+            max_batch_size: 1000,
+            max_line_bytes: 256 * 1024,
+        }),
+        events: web::Data::new(StoreEvents::new()),
+        id_version: web::Data::new(IdVersion::V4),
+        dedupe: web::Data::new(Dedupe::new(false)),
+        soft_delete: web::Data::new(SoftDelete::new(false, chrono::Duration::days(30))),
+        cache: web::Data::new(StoreCache::new(None)),
+        webhook_urls: Arc::new(Vec::new()),
+        max_body_bytes: 256 * 1024,
+        api_keys: Vec::new(),
+        basic_auth: None,
+        jwt: None,
+        rate_limited: false,
+        rate_limiter: web::Data::new(RateLimiter::new(1.0, 1.0)),
+        request_timeout: None,
+        concurrency_limited: false,
+        concurrency_permits: Arc::new(Semaphore::new(1)),
+        cors: CorsSettings::default(),
+        counter1: web::Data::new(AtomicUsize::new(0)),
+    }
+}
+
+/// `test_state()`, returning from the calling test when `TEST_DATABASE_URL` is unset.
+macro_rules! require_db {
+    () => {
+        match test_state() {
+            Some(state) => state,
+            None => return eprintln!("TEST_DATABASE_URL is not set, skipping"),
+        }
+    };
+}
+
+async fn body_json(resp: ServiceResponse) -> Value {
+    serde_json::from_slice(&test::read_body(resp).await).expect("JSON response body")
+}
+
+#[actix_rt::test]
+async fn created_store_is_served_back() {
+    let state = require_db!();
+    let mut app = test::init_service(build_app(&state)).await;
+
+    let req = test::TestRequest::post()
+        .uri("/v1/store")
+        .set_json(&json!({ "name": "a" }))
+        .to_request();
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let created: Value = body_json(resp).await;
+    let created_id = created["api_id"].as_str().expect("api_id in response");
+    assert_eq!(created["data"], json!({ "name": "a" }));
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/v1/store/{}", created_id))
+        .to_request();
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let fetched: Value = body_json(resp).await;
+    assert_eq!(fetched["api_id"], created["api_id"]);
+    assert_eq!(fetched["data"], json!({ "name": "a" }));
+}
+
+#[actix_rt::test]
+async fn repeated_idempotency_key_replays_with_200() {
+    let state = require_db!();
+    let mut app = test::init_service(build_app(&state)).await;
+
+    let mut ids = Vec::new();
+    for expected in &[StatusCode::CREATED, StatusCode::OK] {
+        let req = test::TestRequest::post()
+            .uri("/v1/store")
+            .header("Idempotency-Key", "replay-test")
+            .set_json(&json!({ "n": 1 }))
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), *expected);
+        let body: Value = body_json(resp).await;
+        ids.push(body["api_id"].clone());
+    }
+    assert_eq!(ids[0], ids[1]);
+}
+
+#[actix_rt::test]
+async fn non_object_body_is_rejected() {
+    let state = require_db!();
+    let mut app = test::init_service(build_app(&state)).await;
+
+    let req = test::TestRequest::post()
+        .uri("/v1/store")
+        .set_json(&json!([1, 2]))
+        .to_request();
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_rt::test]
+async fn merge_patch_updates_part_of_the_data() {
+    let state = require_db!();
+    let mut app = test::init_service(build_app(&state)).await;
+
+    let req = test::TestRequest::post()
+        .uri("/v1/store")
+        .set_json(&json!({ "name": "a", "tags": { "x": 1, "y": 2 } }))
+        .to_request();
+    let created = body_json(test::call_service(&mut app, req).await).await;
+    let uri = format!(
+        "/v1/store/{}",
+        created["api_id"].as_str().expect("api_id in response")
+    );
+
+    let req = test::TestRequest::patch()
+        .uri(&uri)
+        .header("Content-Type", "application/merge-patch+json")
+        .set_payload(r#"{"tags":{"x":null,"z":3}}"#)
+        .to_request();
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let patched = body_json(resp).await;
+    assert_eq!(
+        patched["data"],
+        json!({ "name": "a", "tags": { "y": 2, "z": 3 } })
+    );
+    assert_eq!(patched["version"], 2);
+
+    let req = test::TestRequest::patch()
+        .uri(&uri)
+        .set_json(&json!({ "name": "b" }))
+        .to_request();
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+}
+
+#[actix_rt::test]
+async fn json_patch_moves_array_elements() {
+    let state = require_db!();
+    let mut app = test::init_service(build_app(&state)).await;
+
+    let req = test::TestRequest::post()
+        .uri("/v1/store")
+        .set_json(&json!({ "items": ["a", "b"], "done": [] }))
+        .to_request();
+    let created = body_json(test::call_service(&mut app, req).await).await;
+    let uri = format!(
+        "/v1/store/{}",
+        created["api_id"].as_str().expect("api_id in response")
+    );
+
+    let req = test::TestRequest::patch()
+        .uri(&uri)
+        .header("Content-Type", "application/json-patch+json")
+        .set_payload(r#"[{"op":"test","path":"/items/0","value":"a"},{"op":"move","from":"/items/0","path":"/done/-"}]"#)
+        .to_request();
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let patched = body_json(resp).await;
+    assert_eq!(patched["data"], json!({ "items": ["b"], "done": ["a"] }));
+
+    let req = test::TestRequest::patch()
+        .uri(&uri)
+        .header("Content-Type", "application/json-patch+json")
+        .set_payload(r#"[{"op":"test","path":"/items/0","value":"a"}]"#)
+        .to_request();
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+}
+
+#[actix_rt::test]
+async fn retention_deletes_only_expired_stores() {
+    let state = require_db!();
+    let (old, fresh) = {
+        let conn = state.pool.get().expect("pooled connection");
+        let old = insert(&conn, "{}").expect("insert");
+        let fresh = insert(&conn, "{}").expect("insert");
+        let long_ago = chrono::Utc::now().naive_utc() - chrono::Duration::days(40);
+        diesel::update(stores.find(old.id))
+            .set(created_at.eq(long_ago))
+            .execute(&conn)
+            .expect("backdate");
+        (old, fresh)
+    };
+
+    let deleted = crate::retention::prune_once(state.pool.clone(), chrono::Duration::days(30))
+        .await
+        .expect("prune");
+    assert_eq!(deleted, 1);
+
+    let conn = state.pool.get().expect("pooled connection");
+    let remaining: Vec<i32> = stores
+        .filter(id.eq_any(vec![old.id, fresh.id]))
+        .select(id)
+        .load(&conn)
+        .expect("load");
+    assert_eq!(remaining, vec![fresh.id]);
+}
+
+#[actix_rt::test]
+async fn list_returns_a_paginated_envelope() {
+    let state = require_db!();
+    let mut app = test::init_service(build_app(&state)).await;
+
+    for n in 0..3 {
+        let req = test::TestRequest::post()
+            .uri("/v1/store")
+            .set_json(&json!({ "n": n }))
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+    }
+
+    let req = test::TestRequest::get()
+        .uri("/v1/store?limit=2&offset=1")
+        .to_request();
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let page: Value = body_json(resp).await;
+    assert_eq!(page["limit"], 2);
+    assert_eq!(page["offset"], 1);
+    assert!(page["total"].as_i64().expect("total") >= 3);
+    assert_eq!(page["data"].as_array().expect("data array").len(), 2);
+}
+
+#[actix_rt::test]
+async fn keyset_pages_need_a_positive_limit() {
+    let state = require_db!();
+    let mut app = test::init_service(build_app(&state)).await;
+    for n in 0..2 {
+        let req = test::TestRequest::post()
+            .uri("/v1/store")
+            .set_json(&json!({ "n": n }))
+            .to_request();
+        assert_eq!(
+            test::call_service(&mut app, req).await.status(),
+            StatusCode::CREATED
+        );
+    }
+
+    let req = test::TestRequest::get()
+        .uri("/v1/store?after=0&limit=0")
+        .to_request();
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let req = test::TestRequest::get()
+        .uri("/v1/store?after=0&limit=1")
+        .to_request();
+    let page: Value = body_json(test::call_service(&mut app, req).await).await;
+    assert_eq!(page["data"].as_array().expect("data array").len(), 1);
+    assert_eq!(page["next_cursor"], page["data"][0]["id"]);
+}
+
+#[actix_rt::test]
+async fn delete_batch_removes_listed_stores_and_rejects_bad_ids() {
+    let state = require_db!();
+    let mut app = test::init_service(build_app(&state)).await;
+
+    let mut ids = Vec::new();
+    for n in 0..2 {
+        let req = test::TestRequest::post()
+            .uri("/v1/store")
+            .set_json(&json!({ "n": n }))
+            .to_request();
+        let created: Value = body_json(test::call_service(&mut app, req).await).await;
+        ids.push(created["api_id"].clone());
+    }
+
+    let req = test::TestRequest::post()
+        .uri("/v1/store/delete-batch")
+        .set_json(&json!([ids[0], "not-a-uuid"]))
+        .to_request();
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let req = test::TestRequest::post()
+        .uri("/v1/store/delete-batch")
+        .set_json(&json!(ids))
+        .to_request();
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(body_json(resp).await, json!({ "deleted": 2 }));
+}
+
+#[actix_rt::test]
+async fn head_reports_existence_with_the_etag() {
+    let state = require_db!();
+    let mut app = test::init_service(build_app(&state)).await;
+
+    let req = test::TestRequest::post()
+        .uri("/v1/store")
+        .set_json(&json!({ "name": "a" }))
+        .to_request();
+    let created: Value = body_json(test::call_service(&mut app, req).await).await;
+    let uri = format!("/v1/store/{}", created["api_id"].as_str().expect("api_id"));
+
+    let req = test::TestRequest::get().uri(&uri).to_request();
+    let resp = test::call_service(&mut app, req).await;
+    let etag = resp.headers().get(header::ETAG).cloned();
+    assert!(etag.is_some());
+
+    let req = test::TestRequest::default()
+        .method(Method::HEAD)
+        .uri(&uri)
+        .to_request();
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers().get(header::ETAG).cloned(), etag);
+
+    let req = test::TestRequest::default()
+        .method(Method::HEAD)
+        .uri("/v1/store/00000000-0000-0000-0000-000000000000")
+        .to_request();
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_rt::test]
+async fn mutations_are_recorded_in_the_audit_log() {
+    let state = require_db!();
+    let mut app = test::init_service(build_app(&state)).await;
+
+    let req = test::TestRequest::post()
+        .uri("/v1/store")
+        .set_json(&json!({ "n": 1 }))
+        .to_request();
+    let created: Value = body_json(test::call_service(&mut app, req).await).await;
+    let created_id = created["api_id"].as_str().expect("api_id").to_string();
+    let uri = format!("/v1/store/{}", created_id);
+
+    let req = test::TestRequest::put()
+        .uri(&uri)
+        .set_json(&json!({ "n": 2 }))
+        .to_request();
+    assert_eq!(
+        test::call_service(&mut app, req).await.status(),
+        StatusCode::OK
+    );
+    let req = test::TestRequest::delete().uri(&uri).to_request();
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let deleted: Value = body_json(resp).await;
+    assert_eq!(deleted["api_id"], created["api_id"]);
+    assert_eq!(deleted["data"], json!({ "n": 2 }));
+    let req = test::TestRequest::delete().uri(&uri).to_request();
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/v1/audit?api_id={}", created_id))
+        .to_request();
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let page: Value = body_json(resp).await;
+    let actions: Vec<&str> = page["data"]
+        .as_array()
+        .expect("data array")
+        .iter()
+        .filter_map(|entry| entry["action"].as_str())
+        .collect();
+    assert_eq!(actions, vec!["delete", "update", "create"]);
+    assert_eq!(page["total"], 3);
+}
+
+#[actix_rt::test]
+async fn index_increments_both_counters() {
+    let state = offline_state();
+    let mut app = test::init_service(build_app(&state)).await;
+
+    for expected in &[
+        "global counter: 1 local counter: 1",
+        "global counter: 2 local counter: 2",
+    ] {
+        let req = test::TestRequest::get().uri("/").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(test::read_body(resp).await, *expected);
+    }
+}
+
+#[actix_rt::test]
+async fn store_can_be_fetched_by_numeric_id() {
+    let state = require_db!();
+    let mut app = test::init_service(build_app(&state)).await;
+
+    let req = test::TestRequest::post()
+        .uri("/v1/store")
+        .set_json(&json!({ "name": "a" }))
+        .to_request();
+    let created: Value = body_json(test::call_service(&mut app, req).await).await;
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/v1/store/by-id/{}", created["id"]))
+        .to_request();
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(body_json(resp).await["api_id"], created["api_id"]);
+
+    let req = test::TestRequest::get()
+        .uri("/v1/store/by-id/abc")
+        .to_request();
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_rt::test]
+async fn version_reports_the_build() {
+    let state = offline_state();
+    let mut app = test::init_service(build_app(&state)).await;
+
+    let req = test::TestRequest::get().uri("/version").to_request();
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = body_json(resp).await;
+    assert_eq!(body["version"], env!("CARGO_PKG_VERSION"));
+    assert!(body["commit"].is_string());
+    assert!(body["built_at"].is_string());
+}
+
+#[actix_rt::test]
+async fn malformed_json_is_a_400_with_the_parse_error() {
+    let state = offline_state();
+    let mut app = test::init_service(build_app(&state)).await;
+
+    let bodies: [&[u8]; 2] = [b"{\"name\": ", b"{\"not\": \"an array\"}"];
+    for (uri, body) in ["/v1/store", "/v1/store/batch"].iter().zip(bodies.iter()) {
+        let req = test::TestRequest::post()
+            .uri(uri)
+            .header("Content-Type", "application/json")
+            .set_payload(*body)
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let error = body_json(resp).await["error"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+        assert!(error.starts_with("invalid JSON: "), "{}", error);
+    }
+}
+
+#[actix_rt::test]
+async fn dedupe_returns_the_existing_copy_regardless_of_key_order() {
+    let state = require_db!();
+    let mut app = test::init_service(build_app(&state)).await;
+
+    let documents = [
+        json!({ "dedupe": "test", "n": 1 }),
+        json!({ "n": 1, "dedupe": "test" }),
+    ];
+    let mut responses = Vec::new();
+    for document in &documents {
+        let req = test::TestRequest::post()
+            .uri("/v1/store")
+            .header("X-Dedupe", "true")
+            .set_json(document)
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        responses.push((resp.status(), body_json(resp).await["api_id"].clone()));
+    }
+    assert_eq!(responses[0].0, StatusCode::CREATED);
+    assert_eq!(responses[1].0, StatusCode::OK);
+    assert_eq!(responses[0].1, responses[1].1);
+
+    // without the header a copy is inserted as before
+    let req = test::TestRequest::post()
+        .uri("/v1/store")
+        .set_json(&documents[0])
+        .to_request();
+    assert_eq!(
+        test::call_service(&mut app, req).await.status(),
+        StatusCode::CREATED
+    );
+}
+
+#[test]
+fn with_transaction_rolls_back_when_the_body_fails() {
+    let state = require_db!();
+    let now = chrono::Utc::now().naive_utc();
+    let uuid = uuid::Uuid::new_v4().to_string();
+    let result: Result<(), ApiError> = db::with_transaction(&state.pool, |conn| {
+        diesel::insert_into(stores)
+            .values(&NewStore {
+                data: "{}",
+                api_id: &uuid,
+                created_at: now,
+                updated_at: now,
+                idempotency_key: None,
+                content_hash: None,
+            })
+            .execute(conn)?;
+        Err(ApiError::Conflict("abort".to_string()))
+    });
+    assert!(matches!(result, Err(ApiError::Conflict(_))));
+
+    let conn = state.pool.get().expect("a pooled connection");
+    let count = stores
+        .filter(api_id.eq(&uuid))
+        .count()
+        .get_result::<i64>(&conn);
+    assert_eq!(count.expect("count query"), 0);
+}
+
+#[actix_rt::test]
+async fn upsert_creates_then_replaces() {
+    let state = require_db!();
+    let mut app = test::init_service(build_app(&state)).await;
+    let uuid = uuid::Uuid::new_v4().to_string();
+    let uri = format!("/v1/store/{}/upsert", uuid);
+
+    let req = test::TestRequest::put()
+        .uri(&uri)
+        .set_json(&json!({ "n": 1 }))
+        .to_request();
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let created: Value = body_json(resp).await;
+    assert_eq!(created["api_id"], uuid.as_str());
+    assert_eq!(created["version"], 1);
+
+    let req = test::TestRequest::put()
+        .uri(&uri)
+        .set_json(&json!({ "n": 2 }))
+        .to_request();
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let replaced: Value = body_json(resp).await;
+    assert_eq!(replaced["id"], created["id"]);
+    assert_eq!(replaced["data"], json!({ "n": 2 }));
+    assert_eq!(replaced["version"], 2);
+}
+
+#[actix_rt::test]
+async fn search_ranks_matching_stores() {
+    let state = require_db!();
+    let mut app = test::init_service(build_app(&state)).await;
+    for document in &[
+        json!({ "note": "the quick brown fox" }),
+        json!({ "note": "a fox chased another fox", "tag": "fox" }),
+        json!({ "note": "nothing relevant" }),
+    ] {
+        let req = test::TestRequest::post()
+            .uri("/v1/store")
+            .set_json(document)
+            .to_request();
+        assert_eq!(
+            test::call_service(&mut app, req).await.status(),
+            StatusCode::CREATED
+        );
+    }
+
+    let req = test::TestRequest::get()
+        .uri("/v1/store/search?q=foxes")
+        .to_request();
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let page: Value = body_json(resp).await;
+    assert_eq!(page["total"], 2);
+    assert_eq!(page["data"][0]["data"]["tag"], "fox");
+
+    let req = test::TestRequest::get()
+        .uri("/v1/store/search?q=%20")
+        .to_request();
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_rt::test]
+async fn search_ignores_field_names() {
+    let state = require_db!();
+    let mut app = test::init_service(build_app(&state)).await;
+    let req = test::TestRequest::post()
+        .uri("/v1/store")
+        .set_json(&json!({ "name": "Alice", "address": { "city": "Paris" } }))
+        .to_request();
+    assert_eq!(
+        test::call_service(&mut app, req).await.status(),
+        StatusCode::CREATED
+    );
+
+    for (q, total) in &[("address", 0), ("name", 0), ("paris", 1)] {
+        let req = test::TestRequest::get()
+            .uri(&format!("/v1/store/search?q={}", q))
+            .to_request();
+        let page = body_json(test::call_service(&mut app, req).await).await;
+        assert_eq!(page["total"], *total, "q={}", q);
+    }
+}
+
+#[actix_rt::test]
+async fn liveness_and_readiness_probes() {
+    let state = require_db!();
+    let mut app = test::init_service(build_app(&state)).await;
+    for uri in &["/health/live", "/health/ready", "/health"] {
+        let req = test::TestRequest::get().uri(uri).to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK, "{}", uri);
+        assert_eq!(body_json(resp).await["status"], "ok");
+    }
+}
+
+#[actix_rt::test]
+async fn reads_answer_xml_when_preferred() {
+    let state = require_db!();
+    let mut app = test::init_service(build_app(&state)).await;
+    let req = test::TestRequest::post()
+        .uri("/v1/store")
+        .set_json(&json!({ "format": "xml" }))
+        .to_request();
+    let created: Value = body_json(test::call_service(&mut app, req).await).await;
+    let uri = format!("/v1/store/{}", created["api_id"].as_str().expect("api_id"));
+
+    let req = test::TestRequest::get()
+        .uri(&uri)
+        .header(header::ACCEPT, "application/xml")
+        .to_request();
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        resp.headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok()),
+        Some("application/xml; charset=utf-8")
+    );
+    let xml_etag = resp.headers().get(header::ETAG).cloned().expect("ETag");
+    let body = String::from_utf8(test::read_body(resp).await.to_vec()).expect("UTF-8");
+    assert!(
+        body.contains(r#"<data><![CDATA[{"format":"xml"}]]></data>"#),
+        "{}",
+        body
+    );
+
+    // the JSON representation carries a different validator
+    let req = test::TestRequest::get()
+        .uri(&uri)
+        .header(header::IF_NONE_MATCH, xml_etag)
+        .to_request();
+    assert_eq!(
+        test::call_service(&mut app, req).await.status(),
+        StatusCode::OK
+    );
+
+    let req = test::TestRequest::get()
+        .uri("/v1/store?limit=1")
+        .header(header::ACCEPT, "text/xml")
+        .to_request();
+    let body = test::read_body(test::call_service(&mut app, req).await).await;
+    assert!(body.starts_with(br#"<?xml version="1.0" encoding="UTF-8"?><stores total=""#));
+}
+
+#[actix_rt::test]
+async fn soft_deleted_stores_are_hidden_until_purged() {
+    let mut state = require_db!();
+    state.soft_delete = web::Data::new(SoftDelete::new(true, chrono::Duration::days(30)));
+    let mut app = test::init_service(build_app(&state)).await;
+
+    let key = uuid::Uuid::new_v4().to_string();
+    let req = test::TestRequest::post()
+        .uri("/v1/store")
+        .header("Idempotency-Key", key.as_str())
+        .set_json(&json!({ "soft": "gone" }))
+        .to_request();
+    let created: Value = body_json(test::call_service(&mut app, req).await).await;
+    let uri = format!("/v1/store/{}", created["api_id"].as_str().expect("api_id"));
+
+    let req = test::TestRequest::delete().uri(&uri).to_request();
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(body_json(resp).await["deleted_at"].is_string());
+
+    let req = test::TestRequest::get().uri(&uri).to_request();
+    assert_eq!(
+        test::call_service(&mut app, req).await.status(),
+        StatusCode::NOT_FOUND
+    );
+    let req = test::TestRequest::delete().uri(&uri).to_request();
+    assert_eq!(
+        test::call_service(&mut app, req).await.status(),
+        StatusCode::NOT_FOUND
+    );
+
+    // the key of a deleted store no longer replays it
+    let req = test::TestRequest::post()
+        .uri("/v1/store")
+        .header("Idempotency-Key", key.as_str())
+        .set_json(&json!({ "soft": "again" }))
+        .to_request();
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    assert_ne!(body_json(resp).await["api_id"], created["api_id"]);
+
+    let req = test::TestRequest::get()
+        .uri("/v1/store?key=soft&value=gone")
+        .to_request();
+    assert_eq!(
+        body_json(test::call_service(&mut app, req).await).await["total"],
+        0
+    );
+    let req = test::TestRequest::get()
+        .uri("/v1/store?key=soft&value=gone&include_deleted=true")
+        .to_request();
+    let page: Value = body_json(test::call_service(&mut app, req).await).await;
+    assert_eq!(page["total"], 1);
+    assert!(page["data"][0]["deleted_at"].is_string());
+
+    // inside the grace period nothing is purged
+    let purged = crate::retention::purge_once(state.pool.clone(), chrono::Duration::days(30))
+        .await
+        .expect("purge");
+    assert_eq!(purged, 0);
+    let purged = crate::retention::purge_once(state.pool.clone(), chrono::Duration::zero())
+        .await
+        .expect("purge");
+    assert_eq!(purged, 1);
+}
+
+#[actix_rt::test]
+async fn updates_keep_the_previous_value_in_the_history() {
+    let state = require_db!();
+    let mut app = test::init_service(build_app(&state)).await;
+
+    let req = test::TestRequest::post()
+        .uri("/v1/store")
+        .set_json(&json!({ "n": 1 }))
+        .to_request();
+    let created: Value = body_json(test::call_service(&mut app, req).await).await;
+    let uri = format!("/v1/store/{}", created["api_id"].as_str().expect("api_id"));
+
+    let req = test::TestRequest::put()
+        .uri(&uri)
+        .set_json(&json!({ "n": 2 }))
+        .to_request();
+    assert_eq!(
+        test::call_service(&mut app, req).await.status(),
+        StatusCode::OK
+    );
+    let req = test::TestRequest::patch()
+        .uri(&uri)
+        .header(header::CONTENT_TYPE, "application/merge-patch+json")
+        .set_payload(r#"{"n": 3}"#)
+        .to_request();
+    assert_eq!(
+        test::call_service(&mut app, req).await.status(),
+        StatusCode::OK
+    );
+    let req = test::TestRequest::put()
+        .uri(&format!("{}/upsert", uri))
+        .set_json(&json!({ "n": 4 }))
+        .to_request();
+    assert_eq!(
+        test::call_service(&mut app, req).await.status(),
+        StatusCode::OK
+    );
+    // a rejected update leaves no revision behind
+    let req = test::TestRequest::put()
+        .uri(&uri)
+        .header(header::IF_MATCH, "1")
+        .set_json(&json!({ "n": 5 }))
+        .to_request();
+    assert_eq!(
+        test::call_service(&mut app, req).await.status(),
+        StatusCode::CONFLICT
+    );
+
+    let req = test::TestRequest::get()
+        .uri(&format!("{}/history", uri))
+        .to_request();
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let page: Value = body_json(resp).await;
+    assert_eq!(page["total"], 3);
+    let values: Vec<(Value, Value)> = page["data"]
+        .as_array()
+        .expect("data array")
+        .iter()
+        .map(|revision| (revision["version"].clone(), revision["data"]["n"].clone()))
+        .collect();
+    assert_eq!(
+        values,
+        vec![
+            (json!(3), json!(3)),
+            (json!(2), json!(2)),
+            (json!(1), json!(1))
+        ]
+    );
+
+    let req = test::TestRequest::get()
+        .uri(&format!("{}/history?limit=1&offset=2", uri))
+        .to_request();
+    let page: Value = body_json(test::call_service(&mut app, req).await).await;
+    assert_eq!(page["data"][0]["version"], 1);
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/v1/store/{}/history", uuid::Uuid::new_v4()))
+        .to_request();
+    assert_eq!(
+        test::call_service(&mut app, req).await.status(),
+        StatusCode::NOT_FOUND
+    );
+}
+
+#[actix_rt::test]
+async fn malformed_query_strings_get_an_error_body() {
+    let state = offline_state();
+    let mut app = test::init_service(build_app(&state)).await;
+    let req = test::TestRequest::get()
+        .uri("/v1/store?limit=many")
+        .to_request();
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let body = body_json(resp).await;
+    assert_eq!(body["code"], "bad_request");
+    assert!(body["error"]
+        .as_str()
+        .expect("error message")
+        .starts_with("invalid query string"));
+}
+
+#[actix_rt::test]
+async fn timed_out_requests_are_a_504_in_the_error_format() {
+    let mut state = require_db!();
+    state.request_timeout = Some(std::time::Duration::from_millis(50));
+    // the only pooled connection is taken, so the handler waits past the deadline
+    let held = state.pool.get().expect("pooled connection");
+    let mut app = test::init_service(build_app(&state)).await;
+
+    let req = test::TestRequest::get().uri("/v1/store/count").to_request();
+    let err = app.call(req).await.expect_err("timed out");
+    let resp = ServiceResponse::new(
+        test::TestRequest::default().to_http_request(),
+        err.as_response_error().error_response(),
+    );
+    assert_eq!(resp.status(), StatusCode::GATEWAY_TIMEOUT);
+    assert_eq!(body_json(resp).await["code"], "timeout");
+    drop(held);
+}
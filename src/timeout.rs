@@ -0,0 +1,133 @@
+use crate::error::ApiError;
+use actix_service::{Service, Transform};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::Error;
+use futures::future::{ok, Ready};
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+/// Middleware answering `504 Gateway Timeout` when a handler takes longer than `limit`.
+///
+/// The handler future is dropped on timeout, cancelling it at its next await point;
+/// blocking database calls already handed to the thread pool still run to completion.
+/// Only producing the response head is timed, so streamed bodies are not cut off.
+///
+/// The 504 is returned as an `ApiError`, rendered in `ERROR_FORMAT` by the server:
+/// actix-web 2 cannot rebuild the `HttpRequest` the dropped handler owned, so outer
+/// middleware pass the error on without adding their headers.
+#[derive(Clone)]
+pub struct RequestTimeout {
+    limit: Duration,
+    exempt: Rc<Vec<String>>,
+}
+
+impl RequestTimeout {
+    pub fn new(limit: Duration) -> Self {
+        RequestTimeout {
+            limit,
+            exempt: Rc::new(Vec::new()),
+        }
+    }
+
+    /// Let requests for `path` run without a deadline.
+    pub fn exempt(mut self, path: &str) -> Self {
+        Rc::make_mut(&mut self.exempt).push(path.to_string());
+        self
+    }
+}
+
+impl<S, B> Transform<S> for RequestTimeout
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RequestTimeoutMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RequestTimeoutMiddleware {
+            service,
+            timeout: self.clone(),
+        })
+    }
+}
+
+pub struct RequestTimeoutMiddleware<S> {
+    service: S,
+    timeout: RequestTimeout,
+}
+
+impl<S, B> Service for RequestTimeoutMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        if self.timeout.exempt.iter().any(|path| path == req.path()) {
+            return Box::pin(self.service.call(req));
+        }
+        let limit = self.timeout.limit;
+        let fut = self.service.call(req);
+        Box::pin(async move {
+            match actix_rt::time::timeout(limit, fut).await {
+                Ok(res) => res,
+                Err(_) => Err(ApiError::Timeout.into()),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RequestTimeout;
+    use actix_service::Service;
+    use actix_web::http::StatusCode;
+    use actix_web::{test, web, App, HttpResponse};
+    use std::time::Duration;
+
+    async fn slow() -> HttpResponse {
+        actix_rt::time::delay_for(Duration::from_millis(200)).await;
+        HttpResponse::Ok().finish()
+    }
+
+    #[actix_rt::test]
+    async fn slow_handler_gets_504_unless_exempt() {
+        let mut app = test::init_service(
+            App::new()
+                .wrap(RequestTimeout::new(Duration::from_millis(20)).exempt("/stream"))
+                .route("/slow", web::get().to(slow))
+                .route("/stream", web::get().to(slow)),
+        )
+        .await;
+
+        // the error is rendered into a 504 by the server, outside the middleware stack
+        let req = test::TestRequest::get().uri("/slow").to_request();
+        let err = app.call(req).await.expect_err("timed out");
+        assert_eq!(
+            err.as_response_error().status_code(),
+            StatusCode::GATEWAY_TIMEOUT
+        );
+
+        let req = test::TestRequest::get().uri("/stream").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+}
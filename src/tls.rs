@@ -0,0 +1,52 @@
+use crate::config::Settings;
+use rustls::internal::pemfile::{certs, pkcs8_private_keys, rsa_private_keys};
+use rustls::{NoClientAuth, PrivateKey, ServerConfig};
+use std::fs::File;
+use std::io::{self, BufReader};
+
+/// Build a rustls config when `TLS_CERT_PATH` and `TLS_KEY_PATH` are both set.
+///
+/// Returns `Ok(None)` when neither is set so the server falls back to plaintext.
+pub fn config_from_settings(settings: &Settings) -> io::Result<Option<ServerConfig>> {
+    match (&settings.tls_cert_path, &settings.tls_key_path) {
+        (Some(cert_path), Some(key_path)) => load_config(cert_path, key_path).map(Some),
+        (None, None) => Ok(None),
+        _ => Err(io::Error::other(
+            "TLS_CERT_PATH and TLS_KEY_PATH must be set together",
+        )),
+    }
+}
+
+fn open(path: &str) -> io::Result<BufReader<File>> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|e| io::Error::other(format!("could not open {}: {}", path, e)))
+}
+
+/// Load the private key, accepting PKCS#8 or RSA PEM encodings.
+fn load_key(path: &str) -> io::Result<PrivateKey> {
+    let invalid = || io::Error::other(format!("{} does not contain a valid private key", path));
+    let mut keys = pkcs8_private_keys(&mut open(path)?).map_err(|_| invalid())?;
+    if keys.is_empty() {
+        keys = rsa_private_keys(&mut open(path)?).map_err(|_| invalid())?;
+    }
+    keys.into_iter().next().ok_or_else(invalid)
+}
+
+pub fn load_config(cert_path: &str, key_path: &str) -> io::Result<ServerConfig> {
+    let chain = certs(&mut open(cert_path)?)
+        .map_err(|_| io::Error::other(format!("{} is not a valid PEM file", cert_path)))?;
+    if chain.is_empty() {
+        return Err(io::Error::other(format!(
+            "{} does not contain any certificate",
+            cert_path
+        )));
+    }
+    let key = load_key(key_path)?;
+
+    let mut config = ServerConfig::new(NoClientAuth::new());
+    config
+        .set_single_cert(chain, key)
+        .map_err(|e| io::Error::other(format!("invalid certificate or key: {}", e)))?;
+    Ok(config)
+}
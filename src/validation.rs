@@ -0,0 +1,66 @@
+use crate::config::Settings;
+use crate::error::ApiError;
+use jsonschema::Validator;
+use serde_json::Value;
+use std::io;
+
+/// Store data must be a JSON object; `null`, scalars and arrays are rejected.
+pub fn require_object(value: &Value) -> Result<(), ApiError> {
+    if value.is_object() {
+        Ok(())
+    } else {
+        Err(ApiError::BadRequest(
+            "body must be a JSON object".to_string(),
+        ))
+    }
+}
+
+/// Optional JSON Schema that incoming store data must satisfy.
+///
+/// Loaded once at startup from the file named by `STORE_SCHEMA_PATH`; when the
+/// variable is unset every document is accepted.
+pub struct StoreSchema {
+    validator: Option<Validator>,
+}
+
+/// Accepts every document, as when `STORE_SCHEMA_PATH` is unset.
+impl Default for StoreSchema {
+    fn default() -> Self {
+        StoreSchema { validator: None }
+    }
+}
+
+impl StoreSchema {
+    pub fn from_settings(settings: &Settings) -> io::Result<Self> {
+        let path = match &settings.store_schema_path {
+            Some(path) => path,
+            None => return Ok(StoreSchema { validator: None }),
+        };
+        let raw = std::fs::read_to_string(path)
+            .map_err(|e| io::Error::other(format!("could not read schema {}: {}", path, e)))?;
+        let schema: Value = serde_json::from_str(&raw)
+            .map_err(|e| io::Error::other(format!("schema {} is not valid JSON: {}", path, e)))?;
+        let validator = jsonschema::validator_for(&schema)
+            .map_err(|e| io::Error::other(format!("schema {} is invalid: {}", path, e)))?;
+        Ok(StoreSchema {
+            validator: Some(validator),
+        })
+    }
+
+    /// Check `instance` against the schema, collecting every violation.
+    pub fn validate(&self, instance: &Value) -> Result<(), ApiError> {
+        let validator = match &self.validator {
+            Some(validator) => validator,
+            None => return Ok(()),
+        };
+        let errors: Vec<String> = validator
+            .iter_errors(instance)
+            .map(|err| format!("{}: {}", err.instance_path(), err))
+            .collect();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ApiError::Validation(errors))
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use crate::config::Settings;
+use reqwest::{Client, Url};
+use serde::Serialize;
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Header naming the event a webhook delivery describes.
+const EVENT_HEADER: &str = "X-Webhook-Event";
+
+/// Deliveries attempted per URL before giving up.
+const MAX_ATTEMPTS: u32 = 3;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Parse `WEBHOOK_URLS`; an empty list means no webhooks.
+pub fn urls_from_settings(settings: &Settings) -> io::Result<Vec<Url>> {
+    settings
+        .webhook_urls
+        .iter()
+        .map(|url| {
+            Url::parse(url).map_err(|e| {
+                io::Error::other(format!("invalid WEBHOOK_URLS entry {:?}: {}", url, e))
+            })
+        })
+        .collect()
+}
+
+/// Best-effort outbound notifications, built once per worker.
+///
+/// Each worker runs its own runtime and reqwest's connection pool is tied to the
+/// runtime it was first used on, so the client is not shared across workers.
+pub struct Webhooks {
+    client: Client,
+    urls: Arc<Vec<Url>>,
+}
+
+impl Webhooks {
+    pub fn new(urls: Arc<Vec<Url>>) -> Self {
+        let client = Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .expect("static reqwest configuration is valid");
+        Webhooks { client, urls }
+    }
+
+    /// POST `payload` to every configured URL on a spawned task.
+    ///
+    /// Returns immediately; failures are retried with backoff and then logged.
+    pub fn notify<T: Serialize>(&self, event: &'static str, payload: &T) {
+        if self.urls.is_empty() {
+            return;
+        }
+        let body = match serde_json::to_vec(payload) {
+            Ok(body) => body,
+            Err(e) => {
+                log::error!("could not serialize {} webhook: {}", event, e);
+                return;
+            }
+        };
+        for url in self.urls.iter() {
+            actix_rt::spawn(deliver(
+                self.client.clone(),
+                url.clone(),
+                event,
+                body.clone(),
+            ));
+        }
+    }
+}
+
+async fn deliver(client: Client, url: Url, event: &'static str, body: Vec<u8>) {
+    for attempt in 1..=MAX_ATTEMPTS {
+        let result = client
+            .post(url.clone())
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(EVENT_HEADER, event)
+            .body(body.clone())
+            .send()
+            .await
+            .and_then(|resp| resp.error_for_status());
+        match result {
+            Ok(_) => return,
+            Err(e) if attempt == MAX_ATTEMPTS => {
+                log::warn!(
+                    "{} webhook to {} failed after {} attempts: {}",
+                    event,
+                    url,
+                    attempt,
+                    e
+                );
+            }
+            Err(_) => actix_rt::time::delay_for(Duration::from_secs(1 << (attempt - 1))).await,
+        }
+    }
+}
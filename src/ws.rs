@@ -0,0 +1,169 @@
+use crate::events::{next_event, StoreEvent, StoreEvents};
+use crate::store_id;
+use actix::{Actor, ActorContext, AsyncContext, StreamHandler};
+use actix_web::{web, Error, HttpRequest, HttpResponse};
+use actix_web_actors::ws;
+use futures::stream;
+use serde_derive::Deserialize;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::Receiver;
+
+/// How often the server pings the client.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Connections silent for longer than this are closed.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Message a client sends to narrow the feed; `{"subscribe": null}` widens it again.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Command {
+    subscribe: Option<String>,
+}
+
+/// One WebSocket client of the store event feed.
+struct StoreSocket {
+    events: Option<Receiver<StoreEvent>>,
+    /// only events for this api_id are sent when set
+    filter: Option<String>,
+    last_heard: Instant,
+}
+
+impl StoreSocket {
+    fn command(&mut self, text: &str) -> Result<(), &'static str> {
+        let command: Command = serde_json::from_str(text)
+            .map_err(|_| "expected {\"subscribe\": \"<api_id>\"} or {\"subscribe\": null}")?;
+        self.filter = match command.subscribe {
+            Some(raw) => Some(store_id::normalize(&raw).ok_or("subscribe must be a UUID")?),
+            None => None,
+        };
+        Ok(())
+    }
+}
+
+impl Actor for StoreSocket {
+    type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        if let Some(events) = self.events.take() {
+            ctx.add_stream(stream::unfold(events, next_event));
+        }
+        ctx.run_interval(HEARTBEAT_INTERVAL, |socket, ctx| {
+            if socket.last_heard.elapsed() > CLIENT_TIMEOUT {
+                log::info!(
+                    "closing store websocket after {:?} of silence",
+                    CLIENT_TIMEOUT
+                );
+                ctx.stop();
+            } else {
+                ctx.ping(b"");
+            }
+        });
+    }
+}
+
+/// Events from the broadcast channel; the stream ends, closing the socket, once this
+/// client falls too far behind.
+impl StreamHandler<StoreEvent> for StoreSocket {
+    fn handle(&mut self, event: StoreEvent, ctx: &mut Self::Context) {
+        if self
+            .filter
+            .as_ref()
+            .is_none_or(|wanted| *wanted == event.api_id)
+        {
+            ctx.text(event.message);
+        }
+    }
+}
+
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for StoreSocket {
+    fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        let msg = match msg {
+            Ok(msg) => msg,
+            Err(e) => {
+                log::warn!("store websocket protocol error: {}", e);
+                return ctx.stop();
+            }
+        };
+        self.last_heard = Instant::now();
+        match msg {
+            ws::Message::Ping(payload) => ctx.pong(&payload),
+            ws::Message::Text(text) => {
+                if let Err(error) = self.command(&text) {
+                    ctx.text(json!({ "error": error }).to_string());
+                }
+            }
+            ws::Message::Close(reason) => {
+                ctx.close(reason);
+                ctx.stop();
+            }
+            _ => {}
+        }
+    }
+}
+
+/// `GET /v1/store/ws`, a WebSocket pushing a JSON message for every created, updated
+/// and deleted store.
+pub async fn store_socket(
+    req: HttpRequest,
+    stream: web::Payload,
+    events: web::Data<StoreEvents>,
+) -> Result<HttpResponse, Error> {
+    let socket = StoreSocket {
+        events: Some(events.subscribe()),
+        filter: None,
+        last_heard: Instant::now(),
+    };
+    ws::start(socket, &req, stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::store_socket;
+    use crate::events::StoreEvents;
+    use actix_web::{test, web, App};
+    use actix_web_actors::ws::{Frame, Message};
+    use futures::{SinkExt, StreamExt};
+    use serde_json::Value;
+
+    #[actix_rt::test]
+    async fn subscribed_client_only_hears_about_its_store() {
+        let events = web::Data::new(StoreEvents::new());
+        let shared = events.clone();
+        let mut srv = test::start(move || {
+            App::new()
+                .app_data(shared.clone())
+                .route("/ws", web::get().to(store_socket))
+        });
+        let mut socket = srv.ws_at("/ws").await.expect("websocket handshake");
+
+        let wanted = "5f0c3c9e-5a8a-4a52-9d8e-1f6f3f1f2b7a";
+        let subscribe = json!({ "subscribe": wanted }).to_string();
+        socket.send(Message::Text(subscribe)).await.expect("send");
+        // frames are handled in order, so the pong means the subscription is in place
+        socket
+            .send(Message::Ping("sync".into()))
+            .await
+            .expect("send");
+        loop {
+            match socket.next().await {
+                Some(Ok(Frame::Pong(_))) => break,
+                Some(Ok(_)) => continue,
+                other => panic!("socket closed before pong: {:?}", other.is_some()),
+            }
+        }
+
+        events.deleted("00000000-0000-0000-0000-000000000000");
+        events.deleted(wanted);
+        let message = loop {
+            match socket.next().await {
+                Some(Ok(Frame::Text(text))) => break text,
+                Some(Ok(_)) => continue,
+                other => panic!("socket closed before event: {:?}", other.is_some()),
+            }
+        };
+        let message: Value = serde_json::from_slice(&message).expect("JSON message");
+        assert_eq!(message["event"], "deleted");
+        assert_eq!(message["api_id"], wanted);
+    }
+}
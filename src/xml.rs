@@ -0,0 +1,188 @@
+use crate::error::ApiError;
+use crate::model::{KeysetPage, Paginated, StoreResponse};
+use actix_web::dev::HttpResponseBuilder;
+use actix_web::http::header::{self, Accept};
+use actix_web::{HttpMessage, HttpRequest, HttpResponse, ResponseError};
+use quick_xml::events::{BytesCData, BytesDecl, BytesEnd, BytesStart, BytesText, Event};
+use quick_xml::Writer;
+use serde::Serialize;
+use std::io;
+
+const XML_CONTENT_TYPE: &str = "application/xml; charset=utf-8";
+
+/// Whether the client's `Accept` header ranks XML above JSON.
+///
+/// JSON wins ties and anything unparseable, so clients sending `*/*` or nothing at all
+/// keep getting JSON.
+pub fn preferred(req: &HttpRequest) -> bool {
+    let accept = match req.get_header::<Accept>() {
+        Some(accept) => accept,
+        None => return false,
+    };
+    let quality = |wanted: &[&str]| {
+        accept
+            .iter()
+            .filter(|item| wanted.contains(&item.item.essence_str()))
+            .map(|item| item.quality)
+            .max()
+    };
+    let xml = quality(&["application/xml", "text/xml"]);
+    let json = quality(&["application/json", "application/*", "*/*"]);
+    match (xml, json) {
+        (Some(xml), Some(json)) => xml > json,
+        (Some(xml), None) => xml > header::q(0),
+        (None, _) => false,
+    }
+}
+
+/// Body that can also be sent as XML.
+pub trait ToXml {
+    fn write_xml(&self, writer: &mut Writer<Vec<u8>>) -> io::Result<()>;
+}
+
+/// Finish `resp` with `body` as XML when the client prefers it, JSON otherwise.
+pub fn respond<T: Serialize + ToXml>(
+    req: &HttpRequest,
+    mut resp: HttpResponseBuilder,
+    body: &T,
+) -> HttpResponse {
+    resp.header(header::VARY, "Accept");
+    if !preferred(req) {
+        return resp.json(body);
+    }
+    match render(body) {
+        Ok(xml) => resp.content_type(XML_CONTENT_TYPE).body(xml),
+        Err(e) => {
+            log::error!("failed to render XML: {}", e);
+            ApiError::Internal.error_response()
+        }
+    }
+}
+
+/// `body` as a standalone XML document.
+pub fn render<T: ToXml>(body: &T) -> io::Result<Vec<u8>> {
+    let mut writer = Writer::new(Vec::new());
+    writer.write_event(Event::Decl(BytesDecl::new("1.0", Some("UTF-8"), None)))?;
+    body.write_xml(&mut writer)?;
+    Ok(writer.into_inner())
+}
+
+fn text_element(writer: &mut Writer<Vec<u8>>, name: &str, value: &str) -> io::Result<()> {
+    writer.write_event(Event::Start(BytesStart::new(name)))?;
+    writer.write_event(Event::Text(BytesText::new(value)))?;
+    writer.write_event(Event::End(BytesEnd::new(name)))
+}
+
+fn stores_element(
+    writer: &mut Writer<Vec<u8>>,
+    start: BytesStart,
+    data: &[StoreResponse],
+) -> io::Result<()> {
+    writer.write_event(Event::Start(start))?;
+    for store in data {
+        store.write_xml(writer)?;
+    }
+    writer.write_event(Event::End(BytesEnd::new("stores")))
+}
+
+/// `<store>` with `data` kept as its JSON text inside CDATA, since JSON keys need not
+/// be valid element names.
+impl ToXml for StoreResponse {
+    fn write_xml(&self, writer: &mut Writer<Vec<u8>>) -> io::Result<()> {
+        writer.write_event(Event::Start(BytesStart::new("store")))?;
+        text_element(writer, "id", &self.id.to_string())?;
+        text_element(writer, "api_id", &self.api_id)?;
+        writer.write_event(Event::Start(BytesStart::new("data")))?;
+        let data = self.data.to_string();
+        // a `]]>` in the data is split across several CDATA sections
+        for section in BytesCData::escaped(&data) {
+            writer.write_event(Event::CData(section))?;
+        }
+        writer.write_event(Event::End(BytesEnd::new("data")))?;
+        text_element(writer, "created_at", &self.created_at.to_string())?;
+        text_element(writer, "updated_at", &self.updated_at.to_string())?;
+        text_element(writer, "version", &self.version.to_string())?;
+        if let Some(deleted) = self.deleted_at {
+            text_element(writer, "deleted_at", &deleted.to_string())?;
+        }
+        writer.write_event(Event::End(BytesEnd::new("store")))
+    }
+}
+
+/// `<stores total=".." limit=".." offset="..">` around each `<store>`.
+impl ToXml for Paginated<StoreResponse> {
+    fn write_xml(&self, writer: &mut Writer<Vec<u8>>) -> io::Result<()> {
+        let start = BytesStart::new("stores").with_attributes([
+            ("total", self.total.to_string().as_str()),
+            ("limit", self.limit.to_string().as_str()),
+            ("offset", self.offset.to_string().as_str()),
+        ]);
+        stores_element(writer, start, &self.data)
+    }
+}
+
+/// `<stores next_cursor="..">` around each `<store>`; the attribute is left out on the
+/// last page.
+impl ToXml for KeysetPage<StoreResponse> {
+    fn write_xml(&self, writer: &mut Writer<Vec<u8>>) -> io::Result<()> {
+        let mut start = BytesStart::new("stores");
+        if let Some(cursor) = self.next_cursor {
+            start.push_attribute(("next_cursor", cursor.to_string().as_str()));
+        }
+        stores_element(writer, start, &self.data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{preferred, render};
+    use crate::model::StoreResponse;
+    use actix_web::http::header;
+    use actix_web::test::TestRequest;
+
+    fn prefers(accept: &str) -> bool {
+        preferred(
+            &TestRequest::default()
+                .header(header::ACCEPT, accept)
+                .to_http_request(),
+        )
+    }
+
+    #[test]
+    fn xml_only_when_ranked_above_json() {
+        assert!(prefers("application/xml"));
+        assert!(prefers("text/xml, application/json;q=0.5"));
+        assert!(!prefers("application/json, application/xml"));
+        assert!(!prefers("*/*"));
+        assert!(!prefers("application/xml;q=0"));
+        assert!(!preferred(&TestRequest::default().to_http_request()));
+    }
+
+    #[test]
+    fn store_data_is_wrapped_in_cdata() {
+        let now = chrono::NaiveDate::from_ymd_opt(2026, 1, 31)
+            .and_then(|date| date.and_hms_opt(12, 0, 0))
+            .expect("valid timestamp");
+        let store = StoreResponse {
+            id: 7,
+            api_id: "a&b".to_string(),
+            data: json!({ "note": "x]]>y" }),
+            created_at: now,
+            updated_at: now,
+            version: 2,
+            deleted_at: None,
+        };
+        let xml = String::from_utf8(render(&store).expect("renders")).expect("UTF-8");
+        assert_eq!(
+            xml,
+            concat!(
+                r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+                "<store><id>7</id><api_id>a&amp;b</api_id>",
+                r#"<data><![CDATA[{"note":"x]]]]><![CDATA[>y"}]]></data>"#,
+                "<created_at>2026-01-31 12:00:00</created_at>",
+                "<updated_at>2026-01-31 12:00:00</updated_at>",
+                "<version>2</version></store>",
+            )
+        );
+    }
+}